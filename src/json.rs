@@ -0,0 +1,184 @@
+// Just enough JSON for the reports this tool writes and the cache it reads back. Numbers are kept
+// as their text, so integers wider than an f64 (file times in nanoseconds) survive the round trip
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn parse_number<T: std::str::FromStr>(&self) -> Option<T> {
+        match self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+// Quoted JSON string literal
+pub fn string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// None if the text isn't a single JSON value
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    parser.chars.peek().is_none().then_some(value)
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).map(|_| ())
+    }
+
+    fn literal(&mut self, rest: &str, value: Value) -> Option<Value> {
+        for expected in rest.chars() {
+            self.chars.next_if_eq(&expected)?;
+        }
+        Some(value)
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.chars.next()? {
+            'n' => self.literal("ull", Value::Null),
+            't' => self.literal("rue", Value::Bool(true)),
+            'f' => self.literal("alse", Value::Bool(false)),
+            '"' => self.string().map(Value::String),
+            '[' => {
+                let mut values = Vec::new();
+                if self.expect(']').is_none() {
+                    loop {
+                        values.push(self.value()?);
+                        if self.expect(',').is_none() {
+                            self.expect(']')?;
+                            break;
+                        }
+                    }
+                }
+                Some(Value::Array(values))
+            }
+            '{' => {
+                let mut fields = Vec::new();
+                if self.expect('}').is_none() {
+                    loop {
+                        self.expect('"')?;
+                        let name = self.string()?;
+                        self.expect(':')?;
+                        fields.push((name, self.value()?));
+                        if self.expect(',').is_none() {
+                            self.expect('}')?;
+                            break;
+                        }
+                    }
+                }
+                Some(Value::Object(fields))
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::from(c);
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    number.push(c);
+                }
+                Some(Value::Number(number))
+            }
+            _ => None,
+        }
+    }
+
+    // The rest of a string whose opening quote was already read
+    fn string(&mut self) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(text),
+                '\\' => match self.chars.next()? {
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    'u' => {
+                        let unit = self.hex4()?;
+                        let code = if (0xd800..0xdc00).contains(&unit) {
+                            self.literal("\\u", Value::Null)?;
+                            let low = self.hex4()?;
+                            0x10000 + ((unit - 0xd800) << 10) + (low.checked_sub(0xdc00)? & 0x3ff)
+                        } else {
+                            unit
+                        };
+                        text.push(char::from_u32(code)?);
+                    }
+                    c => text.push(c),
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut unit = 0;
+        for _ in 0..4 {
+            unit = unit * 16 + self.chars.next()?.to_digit(16)?;
+        }
+        Some(unit)
+    }
+}
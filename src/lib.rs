@@ -0,0 +1,4474 @@
+//! Reading Baldur's Gate 3 mod paks and writing the modsettings.lsx load order.
+//!
+//! [`open_pak`] opens a single pak, [`read_modules`] collects the metadata of every pak in a mods
+//! folder and [`generate_modsettings`] writes a load order into an existing modsettings.lsx.
+use mmap_io::MemoryMappedFile;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    hash::Hash,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::UNIX_EPOCH,
+};
+use topologic::AcyclicDependencyGraph;
+
+pub mod json;
+pub mod lsf;
+pub mod md5;
+pub mod version;
+
+pub use lsf::LSFError;
+use version::Version;
+
+// Spelling of PAKFile that follows Rust naming, for library users
+pub type PakFile = PAKFile;
+
+// Every error the library can return
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Mmap(mmap_io::MmapIoError),
+    Pak(PAKError),
+    ModSettings(ModSettingsError),
+    Lsf(LSFError),
+    Xml(kiss_xml::errors::KissXmlError),
+    Lz4(lz4_flex::block::DecompressError),
+    Utf8(std::str::Utf8Error),
+    OrderFile(String),
+    Overrides(String),
+    // The player profile has no directory under PlayerProfiles, with the profiles there are
+    NoSuchProfile {
+        profile: String,
+        available: Vec<String>,
+    },
+    // Adding the dependency of module on dependency would close a loop, both as "Name (UUID)"
+    DependencyCycle {
+        module: String,
+        dependency: String,
+    },
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Mmap(e) => write!(f, "{e}"),
+            Error::Pak(e) => write!(f, "{e}"),
+            Error::ModSettings(e) => write!(f, "{e}"),
+            Error::Lsf(e) => write!(f, "{e}"),
+            Error::Xml(e) => write!(f, "{e}"),
+            Error::Lz4(e) => write!(f, "{e}"),
+            Error::Utf8(e) => write!(f, "{e}"),
+            Error::OrderFile(e) => write!(f, "{e}"),
+            Error::Overrides(e) => write!(f, "{e}"),
+            Error::NoSuchProfile { profile, available } if available.is_empty() => {
+                write!(
+                    f,
+                    "No player profile named {profile}, and no profiles exist"
+                )
+            }
+            Error::NoSuchProfile { profile, available } => write!(
+                f,
+                "No player profile named {profile}, available profiles: {}",
+                available.join(", ")
+            ),
+            Error::DependencyCycle { module, dependency } => write!(
+                f,
+                "Circular dependency: {module} depends on {dependency}, which already depends on it"
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<mmap_io::MmapIoError> for Error {
+    fn from(e: mmap_io::MmapIoError) -> Self {
+        Error::Mmap(e)
+    }
+}
+
+impl From<PAKError> for Error {
+    fn from(e: PAKError) -> Self {
+        Error::Pak(e)
+    }
+}
+
+impl From<ModSettingsError> for Error {
+    fn from(e: ModSettingsError) -> Self {
+        Error::ModSettings(e)
+    }
+}
+
+impl From<LSFError> for Error {
+    fn from(e: LSFError) -> Self {
+        Error::Lsf(e)
+    }
+}
+
+impl From<kiss_xml::errors::KissXmlError> for Error {
+    fn from(e: kiss_xml::errors::KissXmlError) -> Self {
+        Error::Xml(e)
+    }
+}
+
+impl From<kiss_xml::errors::DoesNotExistError> for Error {
+    fn from(e: kiss_xml::errors::DoesNotExistError) -> Self {
+        Error::Xml(e.into())
+    }
+}
+
+impl From<lz4_flex::block::DecompressError> for Error {
+    fn from(e: lz4_flex::block::DecompressError) -> Self {
+        Error::Lz4(e)
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Error::Utf8(e)
+    }
+}
+
+pub fn open_pak(path: impl AsRef<Path>) -> Result<PAKFile, Error> {
+    PAKFile::open(path.as_ref().to_path_buf())
+}
+
+// Metadata of every readable pak in mods_dir, unreadable paks are skipped and reported in the
+// notices
+pub fn read_modules(mods_dir: impl AsRef<Path>) -> Result<(Vec<Module>, Vec<Notice>), Error> {
+    scan_mods(mods_dir.as_ref(), ScanOptions::default())
+}
+
+// The given modsettings.lsx contents with the mod list replaced by order
+pub fn generate_modsettings(
+    modsettings: &[u8],
+    order: &[ModuleDescription],
+) -> Result<String, Error> {
+    let mut modsettings = ModSettings::parse(modsettings)?;
+    modsettings.set_mods(order.to_vec());
+    Ok(modsettings.to_lsx_string())
+}
+
+// modsettings.lsx as its mod list, kept in step with the document it was read from so everything
+// else in the file (other regions, the version element, ...) is written back untouched
+pub struct ModSettings {
+    document: kiss_xml::dom::Document,
+    mods: Vec<ModuleDescription>,
+    // Whether the file started with a byte order mark, which writing it keeps
+    bom: bool,
+    notices: Vec<Notice>,
+}
+
+impl ModSettings {
+    pub fn parse(reader: impl Read) -> Result<Self, Error> {
+        Self::parse_with(reader, false)
+    }
+
+    // With repair, nodes missing on the way to the mod list are created instead of an error, and
+    // the base game module is added if the list doesn't have one
+    pub fn parse_with(mut reader: impl Read, repair: bool) -> Result<Self, Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut document = parse_lsx(&data)?;
+        let mut notices = Vec::new();
+        let (mods, skipped) = read_modlist(modlist_mut(&mut document, repair, &mut notices)?);
+        notices.extend(skipped);
+
+        let mut modsettings = Self {
+            document,
+            mods,
+            bom: strip_bom(&data).1,
+            notices,
+        };
+
+        if repair
+            && !modsettings
+                .mods
+                .iter()
+                .any(|module| module.name.starts_with(BASE_GAME_MOD_PREFIX))
+        {
+            let base = ModuleDescription::base_game();
+            modsettings.notices.push(Notice::AddedBaseModule {
+                name: base.name.clone(),
+                version: base.display_version(),
+            });
+            modsettings.mods.insert(0, base);
+            modsettings.sync();
+        }
+
+        let bases: Vec<String> = modsettings
+            .mods
+            .iter()
+            .filter(|module| module.name.starts_with(BASE_GAME_MOD_PREFIX))
+            .map(|module| module.name.clone())
+            .collect();
+        if bases.len() > 1
+            && let Ok(base) = modsettings.base_module()
+        {
+            modsettings.notices.push(Notice::SeveralBaseModules {
+                names: bases,
+                used: base.name,
+            });
+        }
+
+        Ok(modsettings)
+    }
+
+    // What parsing repaired, skipped or had to choose
+    pub fn notices(&self) -> &[Notice] {
+        &self.notices
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<(), Error> {
+        writer.write_all(self.to_lsx_string().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_lsx_string(&self) -> String {
+        let lsx = lsx_string(&self.document);
+        if self.bom { format!("{BOM}{lsx}") } else { lsx }
+    }
+
+    pub fn document(&self) -> &kiss_xml::dom::Document {
+        &self.document
+    }
+
+    pub fn mods(&self) -> &[ModuleDescription] {
+        &self.mods
+    }
+
+    pub fn base_module(&self) -> Result<ModuleDescription, Error> {
+        Ok(find_base_module(&self.mods)?)
+    }
+
+    pub fn set_mods(&mut self, mods: Vec<ModuleDescription>) {
+        self.mods = mods;
+        self.sync();
+    }
+
+    // Appends the mod, or replaces the entry already listed with its UUID in place
+    pub fn add(&mut self, module: ModuleDescription) {
+        match self.mods.iter_mut().find(|listed| **listed == module) {
+            Some(listed) => *listed = module,
+            None => self.mods.push(module),
+        }
+        self.sync();
+    }
+
+    pub fn remove(&mut self, uuid: &str) -> Option<ModuleDescription> {
+        let index = self.position(uuid)?;
+        let module = self.mods.remove(index);
+        self.sync();
+        Some(module)
+    }
+
+    // Moves the mod to index (or the end, if past it), false if it isn't listed
+    pub fn move_to(&mut self, uuid: &str, index: usize) -> bool {
+        let Some(from) = self.position(uuid) else {
+            return false;
+        };
+        let module = self.mods.remove(from);
+        self.mods.insert(index.min(self.mods.len()), module);
+        self.sync();
+        true
+    }
+
+    fn position(&self, uuid: &str) -> Option<usize> {
+        self.mods
+            .iter()
+            .position(|module| module.uuid.eq_ignore_ascii_case(uuid))
+    }
+
+    fn sync(&mut self) {
+        // parse made sure the mod list is there
+        if let Ok(modlist) = modlist_mut(&mut self.document, false, &mut Vec::new()) {
+            set_modlist(modlist, &self.mods);
+        }
+    }
+}
+
+// A difference between two mod lists, positions are 1-based like the load order listings
+#[derive(Debug)]
+pub enum ModChange {
+    Added {
+        module: ModuleDescription,
+        position: usize,
+    },
+    Removed {
+        module: ModuleDescription,
+        position: usize,
+    },
+    Moved {
+        module: ModuleDescription,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl Display for ModChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added { module, position } => {
+                write!(f, "+ {} ({}) at {position}", module.name, module.uuid)
+            }
+            Self::Removed { module, position } => {
+                write!(f, "- {} ({}) from {position}", module.name, module.uuid)
+            }
+            Self::Moved { module, from, to } => {
+                write!(f, "~ {} ({}) {from} -> {to}", module.name, module.uuid)
+            }
+        }
+    }
+}
+
+// Mods added to and removed from old, and the mods that changed place. Only mods off the longest
+// run kept in the same relative order count as moved, so one mod moving doesn't shift the rest
+pub fn diff_mods(old: &[ModuleDescription], new: &[ModuleDescription]) -> Vec<ModChange> {
+    let kept_old: Vec<usize> = (0..old.len()).filter(|&i| new.contains(&old[i])).collect();
+    let kept_new: Vec<usize> = (0..new.len()).filter(|&i| old.contains(&new[i])).collect();
+
+    // Longest common subsequence of the mods in both, by UUID
+    let mut lengths = vec![vec![0usize; kept_new.len() + 1]; kept_old.len() + 1];
+    for i in (0..kept_old.len()).rev() {
+        for j in (0..kept_new.len()).rev() {
+            lengths[i][j] = if old[kept_old[i]] == new[kept_new[j]] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut in_place = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < kept_old.len() && j < kept_new.len() {
+        if old[kept_old[i]] == new[kept_new[j]] {
+            in_place.insert(kept_new[j]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    let removed = old
+        .iter()
+        .enumerate()
+        .filter(|(_, module)| !new.contains(module))
+        .map(|(index, module)| ModChange::Removed {
+            module: module.clone(),
+            position: index + 1,
+        });
+    let changed = new.iter().enumerate().filter_map(|(index, module)| {
+        match old.iter().position(|listed| listed == module) {
+            None => Some(ModChange::Added {
+                module: module.clone(),
+                position: index + 1,
+            }),
+            Some(from) if !in_place.contains(&index) => Some(ModChange::Moved {
+                module: module.clone(),
+                from: from + 1,
+                to: index + 1,
+            }),
+            Some(_) => None,
+        }
+    });
+
+    removed.chain(changed).collect()
+}
+
+// The actual game is a "mod" with the prefix "Gustav" (Seems to use both GustavX and GustavDev)
+const BASE_GAME_MOD_PREFIX: &str = "Gustav";
+
+// Modules shipped with the game itself (folder, uuid). Dependencies on any of these are always satisfied
+pub const BASE_GAME_MODULES: &[(&str, &str)] = &[
+    ("Gustav", "991c9c7a-fb80-40cb-8f0d-b92d4e80e9b1"),
+    ("GustavDev", "28ac9ce2-2aba-8cda-b3b5-6e922f71b6b8"),
+    ("GustavX", "cb555efe-2d9e-131f-8195-a89329d218ea"),
+    ("Shared", "ed539163-bb70-431b-96a7-f5b2eda5376b"),
+    ("SharedDev", "3d0c5ff8-c95d-c907-ff3e-34b204f1c630"),
+    ("Engine", "9dff4c3b-fda7-43de-a763-ce1383039999"),
+    ("DiceSet_01", "e842840a-2449-588c-b0c4-22122cfce31b"),
+    ("DiceSet_02", "b176a0ac-d79f-ed9d-5a87-5c2c80874e10"),
+    ("DiceSet_03", "e0a4d990-7b9b-8fa9-d7c6-04017c6cf5b1"),
+    ("DiceSet_04", "77a2155f-4b35-4f0c-e7ff-4338f91426a4"),
+    ("DiceSet_06", "ee4989eb-aab8-968f-8674-812ea2f4bfd7"),
+    ("Honour", "b77b6210-ac50-4cb1-a3d5-5702fb9c744c"),
+    ("HonourX", "767d0062-d82c-279c-e16b-dfee7fe94cdd"),
+    ("MainUI", "630daa32-70f8-3da5-41b9-154fe8410236"),
+    ("ModBrowser", "ee5a55ff-eb38-0b27-c5b0-f358dc306d34"),
+    ("FW3", "e1ce736b-52e6-e713-e9e7-e6abbb15a198"),
+];
+
+// ModuleShortDesc attributes that keep whichever type the mod's meta used (FixedString, LSWString,
+// ... for the strings)
+pub const TYPED_ATTRIBUTES: &[&str] = &["Folder", "MD5", "Name", "Build"];
+
+// Bytes in each file list entry
+const PAK_FILE_ENTRY_SIZE: usize = 272;
+
+// How far into a file to look for an embedded pak's header with --scan-magic
+const PAK_MAGIC_SCAN_LIMIT: u64 = 16 * 1024 * 1024;
+
+// Archive flags: allow memory mapping, solid, preload
+const PAK_KNOWN_FLAGS: u8 = 0x02 | 0x04 | 0x08;
+pub const PAK_FLAG_SOLID: u8 = 0x04;
+
+// Size of the lz4 frame header (magic, flags, block size and checksum) in solid archives, which the
+// offset of their first file points just past
+const SOLID_FRAME_HEADER_SIZE: u64 = 7;
+
+// Attributes come first in this order when writing LSX (id/type/value for attributes,
+// major.minor.revision.build for versions), any others after them by name
+const LSX_ATTRIBUTE_ORDER: &[&str] = &[
+    "id", "type", "value", "handle", "version", "major", "minor", "revision", "build",
+];
+
+// Byte order mark (Sometimes included at the start of an XML file)
+pub const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VersionPolicy {
+    Highest,
+    Lowest,
+    Existing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressionMethod {
+    None,
+    Zlib,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Debug)]
+pub enum PAKError {
+    BadMagic(String),
+    UnsupportedVersion(u32),
+    Unsupported(String),
+    UnsupportedCompression(u8),
+    NoMetadata,
+    NoSuchFile(String),
+    MissingPart(PathBuf),
+    Corrupt {
+        expected: usize,
+        actual: usize,
+    },
+    Truncated {
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch {
+        expected: [u8; 16],
+        actual: [u8; 16],
+    },
+    MissingAttribute {
+        node: String,
+        attr: &'static str,
+    },
+}
+
+impl std::error::Error for PAKError {}
+
+impl Display for PAKError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic(magic) => {
+                write!(f, "Bad header magic value: {magic} (should be \"LSPK\")")
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "Can't read LSPK version {version} (only version 18 is supported)"
+                )
+            }
+            Self::Unsupported(reason) => {
+                write!(f, "Can't read this pak: {reason}")
+            }
+            Self::UnsupportedCompression(method) => {
+                write!(
+                    f,
+                    "Unknown compression method {method} (pass --force-compression if the flags are wrong)"
+                )
+            }
+            Self::NoMetadata => {
+                write!(f, "Could not find meta.lsx file in pak")
+            }
+            Self::NoSuchFile(name) => {
+                write!(f, "Could not find {name} in pak")
+            }
+            Self::MissingPart(path) => {
+                write!(f, "Missing part of a multi part pak: {}", path.display())
+            }
+            Self::Corrupt { expected, actual } => {
+                write!(
+                    f,
+                    "File list is {actual} bytes, expected {expected} (corrupt or unsupported pak)"
+                )
+            }
+            Self::MissingAttribute { node, attr } => {
+                write!(f, "{node} has no {attr} attribute")
+            }
+            Self::ChecksumMismatch { expected, actual } => {
+                let hex = |md5: &[u8; 16]| -> String {
+                    md5.iter().map(|byte| format!("{byte:02x}")).collect()
+                };
+                write!(
+                    f,
+                    "File list MD5 is {}, the header says {} (corrupt or modified pak)",
+                    hex(actual),
+                    hex(expected)
+                )
+            }
+            Self::Truncated { expected, actual } => {
+                write!(
+                    f,
+                    "Pak is {actual} bytes but needs at least {expected} (truncated or corrupt)"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModSettingsError {
+    MissingNode(String),
+    NoBaseModule,
+}
+
+impl std::error::Error for ModSettingsError {}
+
+impl Display for ModSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingNode(node) => {
+                write!(
+                    f,
+                    "modsettings.lsx is missing the {node} node (pass --repair to recreate it)"
+                )
+            }
+            Self::NoBaseModule => {
+                write!(
+                    f,
+                    "Could not find the base game module in modsettings.lsx (launch the game once to generate it, or pass --repair to add it)"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PAKFile {
+    // The pak itself, then the files holding the data of each further part (Name_1.pak, ...)
+    pub parts: Vec<Arc<MemoryMappedFile>>,
+    pub header: PAKHeader,
+    // Empty for paks opened with open_lazy, which read entries() as needed instead
+    pub file_list: Vec<PAKFileEntry>,
+    // Every file of a solid archive, decompressed. The entries in file_list point into it
+    pub solid: Option<Vec<u8>>,
+    // Used for every entry instead of the method in its flags, for paks with wrong flags
+    pub force_compression: Option<CompressionMethod>,
+    // Where the pak starts in the file
+    pub base: u64,
+    // What is_module and module() read, read ahead of time by read_ahead. None if it wasn't (or
+    // couldn't be), Some(None) for paks that aren't mods
+    read_ahead: Option<Option<Vec<u8>>>,
+    // What reading entries worked around, until take_notices
+    notices: Mutex<Vec<Notice>>,
+}
+
+impl Display for PAKFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "PAKFile: {}", self.path().display())?;
+        writeln!(f, "Version: {}", self.header.version)?;
+        if self.parts.len() > 1 {
+            writeln!(f, "Parts: {}", self.parts.len())?;
+        }
+        let stats = self.stats();
+        writeln!(f, "File Count: {}", stats.file_count)?;
+        writeln!(f, "Size On Disk: {}", stats.size_on_disk)?;
+        writeln!(f, "Uncompressed Size: {}", stats.uncompressed_size)
+    }
+}
+
+impl PAKFile {
+    pub fn open(path: PathBuf) -> Result<PAKFile, Error> {
+        Self::open_with(path, false, None)
+    }
+
+    // With scan_magic, a pak appended to another file (e.g. an installer stub) is found by
+    // searching the start of the file for the header magic. Every offset in the pak is then
+    // relative to where it was found
+    pub fn open_with(
+        path: PathBuf,
+        scan_magic: bool,
+        part_dir: Option<&Path>,
+    ) -> Result<PAKFile, Error> {
+        let mut pak = Self::open_lazy(path, scan_magic, part_dir)?;
+        if pak.solid.is_none() {
+            pak.file_list = pak.entries()?.collect::<Result<_, _>>()?;
+        }
+        Ok(pak)
+    }
+
+    // Like open_with, but without reading the file list up front, which for big content paks
+    // takes tens of megabytes when only meta.lsx is wanted
+    pub fn open_lazy(
+        path: PathBuf,
+        scan_magic: bool,
+        part_dir: Option<&Path>,
+    ) -> Result<PAKFile, Error> {
+        let file = MemoryMappedFile::open_ro(path)?;
+
+        let base = if scan_magic {
+            let searched = file.as_slice(0, file.len().min(PAK_MAGIC_SCAN_LIMIT))?;
+            searched
+                .windows(4)
+                .position(|window| window == b"LSPK")
+                .unwrap_or(0) as u64
+        } else {
+            0
+        };
+
+        // Every version starts with the magic and version, the rest of the header depends on it
+        let prefix = checked_slice(&file, base, 8)?;
+        let magic: [u8; 4] = le_bytes(prefix, 0);
+        if magic != *b"LSPK" {
+            return Err(Error::Pak(PAKError::BadMagic(format!("{magic:?}"))));
+        }
+
+        let version = PakVersion::try_from(u32::from_le_bytes(le_bytes(prefix, 4)))?;
+        let header = PAKHeader::parse(
+            version,
+            checked_slice(&file, base, version.header_size() as u64)?,
+        );
+
+        if header.flags & !PAK_KNOWN_FLAGS != 0 {
+            return Err(Error::Pak(PAKError::Unsupported(format!(
+                "unknown archive flags {:#04x}, it may be encrypted or signed",
+                header.flags
+            ))));
+        }
+
+        // Make sure the file list is there, even though it isn't read yet
+        file_list_slice(&file, base, &header)?;
+
+        // Data parts have no header of their own, so offsets into them start at 0 even with
+        // scan_magic
+        let mut parts = vec![Arc::new(file)];
+        for part in 1..header.num_parts {
+            let part_path = part_path(parts[0].path(), part_dir, part);
+            if !part_path.exists() {
+                return Err(Error::Pak(PAKError::MissingPart(part_path)));
+            }
+            parts.push(Arc::new(MemoryMappedFile::open_ro(part_path)?));
+        }
+
+        let mut pak = Self {
+            parts,
+            header,
+            file_list: Vec::new(),
+            solid: None,
+            force_compression: None,
+            base,
+            read_ahead: None,
+            notices: Mutex::default(),
+        };
+
+        // Files of a solid archive can only be read by decompressing all of them, so that (and
+        // reading the file list) happens up front even when opening lazily
+        if pak.header.flags & PAK_FLAG_SOLID != 0 {
+            pak.unpack_solid()?;
+        }
+
+        Ok(pak)
+    }
+
+    // Solid archives compress every file into one lz4 frame, with the entries pointing into the
+    // compressed stream back to back, starting right after the frame header. Decompress it once
+    // and point the entries into the result instead, as if the files were stored uncompressed
+    fn unpack_solid(&mut self) -> Result<(), Error> {
+        let mut entries: Vec<PAKFileEntry> = self.entries()?.collect::<Result<_, _>>()?;
+
+        let start = entries
+            .iter()
+            .map(PAKFileEntry::offset)
+            .min()
+            .unwrap_or(SOLID_FRAME_HEADER_SIZE);
+        let mut end = start;
+        for entry in &entries {
+            if entry.offset() != end || start < SOLID_FRAME_HEADER_SIZE {
+                return Err(Error::Pak(PAKError::Unsupported(String::from(
+                    "solid archive whose files aren't stored back to back",
+                ))));
+            }
+            end += entry.size_on_disk as u64;
+        }
+
+        let frame_start = start - SOLID_FRAME_HEADER_SIZE;
+        let frame = checked_slice(&self.parts[0], self.base + frame_start, end - frame_start)?;
+        let mut data = Vec::new();
+        lz4_flex::frame::FrameDecoder::new(frame)
+            .read_to_end(&mut data)
+            .map_err(|e| {
+                Error::Pak(PAKError::Unsupported(format!(
+                    "solid archive whose data isn't an lz4 frame ({e})"
+                )))
+            })?;
+
+        let mut position: u64 = 0;
+        for entry in &mut entries {
+            entry.offset_in_file_1 = position as u32;
+            entry.offset_in_file_2 = (position >> 32) as u16;
+            entry.archive_part = 0;
+            entry.flags &= !0x0f;
+            entry.size_on_disk = entry.uncompressed_size;
+            position += entry.uncompressed_size as u64;
+        }
+
+        if position != data.len() as u64 {
+            return Err(Error::Pak(PAKError::Corrupt {
+                expected: position as usize,
+                actual: data.len(),
+            }));
+        }
+
+        self.file_list = entries;
+        self.solid = Some(data);
+        Ok(())
+    }
+
+    // The file list decompressed one entry at a time, straight from the pak
+    pub fn entries(&self) -> Result<EntryReader<'_>, Error> {
+        let (file_count, compressed) = file_list_slice(&self.parts[0], self.base, &self.header)?;
+        Ok(EntryReader::new(compressed, file_count))
+    }
+
+    // file_list, or the entries read from the pak if it was opened lazily (an empty list reads
+    // nothing either way)
+    fn each_entry(&self) -> Box<dyn Iterator<Item = Result<Cow<'_, PAKFileEntry>, Error>> + '_> {
+        if !self.file_list.is_empty() {
+            return Box::new(self.file_list.iter().map(|entry| Ok(Cow::Borrowed(entry))));
+        }
+
+        match self.entries() {
+            Ok(entries) => Box::new(entries.map(|entry| entry.map(Cow::Owned))),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+
+    // Entries past a corrupt part of a lazily read file list are left out
+    pub fn stats(&self) -> PAKStats {
+        self.each_entry()
+            .map_while(Result::ok)
+            .fold(PAKStats::default(), |stats, entry| PAKStats {
+                file_count: stats.file_count + 1,
+                size_on_disk: stats.size_on_disk + entry.size_on_disk as u64,
+                uncompressed_size: stats.uncompressed_size + entry.uncompressed_size as u64,
+            })
+    }
+
+    // Every file in the archive. Entries past a corrupt part of a lazily read file list are left out
+    pub fn file_names(&self) -> HashSet<String> {
+        self.each_entry()
+            .map_while(Result::ok)
+            .map(|entry| entry.name())
+            .collect()
+    }
+
+    // Distinct Mods/<Folder> directories in the archive, in order of first appearance
+    pub fn mod_folders(&self) -> Vec<String> {
+        let mut folders: Vec<String> = Vec::new();
+        for entry in &self.file_list {
+            let name = entry.name();
+            if let Some((folder, _)) = name
+                .strip_prefix("Mods/")
+                .and_then(|rest| rest.split_once('/'))
+                && !folders.iter().any(|known| known == folder)
+            {
+                folders.push(folder.to_string());
+            }
+        }
+        folders
+    }
+
+    pub fn find_entry(&self, name: &str) -> Option<&PAKFileEntry> {
+        self.file_list.iter().find(|entry| entry.name() == name)
+    }
+
+    // What reading entries has worked around so far, since the last call
+    pub fn take_notices(&self) -> Vec<Notice> {
+        std::mem::take(&mut self.notices.lock().unwrap())
+    }
+
+    fn notice(&self, notice: Notice) {
+        self.notices.lock().unwrap().push(notice);
+    }
+
+    pub fn read_entry(&self, entry: &PAKFileEntry) -> Result<Vec<u8>, Error> {
+        let method = match self.force_compression {
+            Some(method) => method,
+            None => entry.compression_method()?,
+        };
+
+        if self.force_compression.is_none()
+            && method == CompressionMethod::None
+            && entry.flags & 0x0f != 0
+        {
+            self.notice(Notice::StoredDespiteFlags(entry.name()));
+        }
+
+        // Placeholder files have no data at all, and lz4 rejects an empty block. Stored files
+        // record an uncompressed size of 0 too, so that alone only means empty when compressed
+        if entry.size_on_disk == 0
+            || (entry.uncompressed_size == 0 && method != CompressionMethod::None)
+        {
+            return Ok(Vec::new());
+        }
+
+        if let Some(data) = &self.solid {
+            let start = entry.offset() as usize;
+            let end = start + entry.size_on_disk as usize;
+            return data
+                .get(start..end)
+                .map(<[u8]>::to_vec)
+                .ok_or(Error::Pak(PAKError::Corrupt {
+                    expected: end,
+                    actual: data.len(),
+                }));
+        }
+
+        let Some(part) = self.parts.get(entry.archive_part as usize) else {
+            return Err(Error::Pak(PAKError::MissingPart(part_path(
+                self.path(),
+                None,
+                entry.archive_part as u16,
+            ))));
+        };
+        let base = if entry.archive_part == 0 {
+            self.base
+        } else {
+            0
+        };
+
+        let compressed = part.as_slice(base + entry.offset(), entry.size_on_disk as u64)?;
+
+        let (data, notice) = entry.decompress_entry(compressed, method)?;
+        if let Some(notice) = notice {
+            self.notice(notice);
+        }
+        Ok(data)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.parts[0].path()
+    }
+
+    // Checks the compressed file list against the MD5 in the header, if the packer wrote one
+    pub fn verify_checksum(&self) -> Result<(), Error> {
+        if self.header.md5 == [0; 16] {
+            return Ok(());
+        }
+
+        let (_, file_list) = file_list_slice(&self.parts[0], self.base, &self.header)?;
+
+        let actual = md5::digest(file_list);
+        if actual != self.header.md5 {
+            return Err(Error::Pak(PAKError::ChecksumMismatch {
+                expected: self.header.md5,
+                actual,
+            }));
+        }
+
+        Ok(())
+    }
+
+    // Whether the pak holds a mod at all. Paks with only assets (textures, audio, ...) have no Mods
+    // tree and so no metadata either. A file list that can't be read counts as a mod, so reading
+    // its metadata reports the problem
+    pub fn is_module(&self) -> bool {
+        if let Some(meta) = &self.read_ahead {
+            return meta.is_some();
+        }
+
+        self.each_entry()
+            .any(|entry| entry.map_or(true, |entry| entry.name().starts_with("Mods/")))
+    }
+
+    // meta.lsx, or the compiled meta.lsf some mods ship instead. Stops reading the file list at
+    // meta.lsx
+    pub fn metadata_entry(&self) -> Result<Option<Cow<'_, PAKFileEntry>>, Error> {
+        let mut lsf = None;
+        for entry in self.each_entry() {
+            let entry = entry?;
+            let name = entry.name();
+            if !name.starts_with("Mods/") {
+                continue;
+            }
+
+            if name.ends_with("/meta.lsx") {
+                return Ok(Some(entry));
+            } else if lsf.is_none() && name.ends_with("/meta.lsf") {
+                lsf = Some(entry);
+            }
+        }
+        Ok(lsf)
+    }
+
+    pub fn module(&self) -> Result<Module, Error> {
+        let raw_meta = match &self.read_ahead {
+            Some(Some(meta)) => Cow::Borrowed(meta),
+            _ => Cow::Owned(self.meta_bytes()?),
+        };
+
+        Ok(Module {
+            pak: Some(self.path().to_path_buf()),
+            priority: self.header.priority,
+            ..Module::from_meta_bytes(&raw_meta)?
+        })
+    }
+
+    fn meta_bytes(&self) -> Result<Vec<u8>, Error> {
+        let Some(metadata) = self.metadata_entry()? else {
+            return Err(Error::Pak(PAKError::NoMetadata));
+        };
+
+        self.read_entry(&metadata)
+    }
+
+    // Decompress the file list and meta.lsx now, leaving only parsing it to module(). The parsed XML
+    // can't be sent between threads but the bytes can, so open_paks does this on the thread that
+    // opened the pak. Errors are left for module() to report
+    pub fn read_ahead(&mut self) {
+        self.read_ahead = if self.is_module() {
+            self.meta_bytes().ok().map(Some)
+        } else {
+            Some(None)
+        };
+    }
+}
+
+// Totals over every file in a pak
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PAKStats {
+    pub file_count: usize,
+    pub size_on_disk: u64,
+    pub uncompressed_size: u64,
+}
+
+// LSPK versions this tool can read. Each version lays out the header and file list differently
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PakVersion {
+    // Baldur's Gate 3
+    V18,
+}
+
+impl TryFrom<u32> for PakVersion {
+    type Error = PAKError;
+
+    fn try_from(version: u32) -> Result<Self, Self::Error> {
+        match version {
+            18 => Ok(Self::V18),
+            version => Err(PAKError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+impl Display for PakVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V18 => write!(f, "18"),
+        }
+    }
+}
+
+impl PakVersion {
+    // Bytes in the header, counting the magic and version
+    pub fn header_size(self) -> usize {
+        match self {
+            Self::V18 => 40,
+        }
+    }
+}
+
+// The header fields the rest of the tool uses, whatever version they were read from
+#[derive(Debug)]
+pub struct PAKHeader {
+    pub version: PakVersion,
+    pub file_list_offset: u64,
+    pub flags: u8,
+    pub priority: u8,
+    // Of the compressed file list, all zero if the packer didn't fill it in
+    pub md5: [u8; 16],
+    pub num_parts: u16,
+}
+
+impl PAKHeader {
+    // bytes holds version.header_size() bytes
+    pub fn parse(version: PakVersion, bytes: &[u8]) -> Self {
+        match version {
+            PakVersion::V18 => Self {
+                version,
+                file_list_offset: u64::from_le_bytes(le_bytes(bytes, 8)),
+                // file_list_size (u32) at 16, unused since the file list has its own sizes
+                flags: bytes[20],
+                priority: bytes[21],
+                md5: le_bytes(bytes, 22),
+                num_parts: u16::from_le_bytes(le_bytes(bytes, 38)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PAKFileEntry {
+    pub name: [u8; 256],
+    pub offset_in_file_1: u32,
+    pub offset_in_file_2: u16,
+    pub archive_part: u8,
+    pub flags: u8,
+    pub size_on_disk: u32,
+    pub uncompressed_size: u32,
+}
+
+// Part N of a multi part pak is named <name>_N.pak, next to it unless another directory is given
+fn part_path(path: &Path, dir: Option<&Path>, part: u16) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = format!("{stem}_{part}.pak");
+    match dir {
+        Some(dir) => dir.join(name),
+        None => path.with_file_name(name),
+    }
+}
+
+// The number of entries in the file list, and the lz4 compressed list itself
+fn file_list_slice<'a>(
+    file: &'a MemoryMappedFile,
+    base: u64,
+    header: &PAKHeader,
+) -> Result<(usize, &'a [u8]), Error> {
+    // Literally why
+    let file_list_info = checked_slice(
+        file,
+        base + header.file_list_offset,
+        size_of::<u32>() as u64 * 2,
+    )?;
+
+    let file_count = u32::from_le_bytes(le_bytes(file_list_info, 0)) as usize;
+    let compressed_size = u32::from_le_bytes(le_bytes(file_list_info, 4));
+
+    let compressed = checked_slice(
+        file,
+        base + header.file_list_offset + 8,
+        compressed_size as u64,
+    )?;
+
+    Ok((file_count, compressed))
+}
+
+// len bytes at offset, or Truncated instead of reading past the end of a short file
+fn checked_slice(file: &MemoryMappedFile, offset: u64, len: u64) -> Result<&[u8], Error> {
+    let expected = offset.saturating_add(len);
+    if expected > file.len() {
+        return Err(Error::Pak(PAKError::Truncated {
+            expected,
+            actual: file.len(),
+        }));
+    }
+    Ok(file.as_slice(offset, len)?)
+}
+
+fn le_bytes<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N].try_into().unwrap()
+}
+
+impl PAKFileEntry {
+    // bytes holds PAK_FILE_ENTRY_SIZE bytes, in the on-disk layout whatever the host's byte order
+    pub fn parse(bytes: &[u8]) -> Self {
+        Self {
+            name: le_bytes(bytes, 0),
+            offset_in_file_1: u32::from_le_bytes(le_bytes(bytes, 256)),
+            offset_in_file_2: u16::from_le_bytes(le_bytes(bytes, 260)),
+            archive_part: bytes[262],
+            flags: bytes[263],
+            size_on_disk: u32::from_le_bytes(le_bytes(bytes, 264)),
+            uncompressed_size: u32::from_le_bytes(le_bytes(bytes, 268)),
+        }
+    }
+
+    pub fn compression_method(&self) -> Result<CompressionMethod, PAKError> {
+        // Some packers leave the compression flags on files they ended up storing, which take the
+        // same space on disk as uncompressed
+        if self.size_on_disk == self.uncompressed_size {
+            return Ok(CompressionMethod::None);
+        }
+
+        // The high nibble is the compression level (fast, default or max), which reading ignores
+        match self.flags & 0x0f {
+            0 => Ok(CompressionMethod::None),
+            1 => Ok(CompressionMethod::Zlib),
+            2 => Ok(CompressionMethod::Lz4),
+            3 => Ok(CompressionMethod::Zstd),
+            method => Err(PAKError::UnsupportedCompression(method)),
+        }
+    }
+
+    // Packers that align entries count the padding in size_on_disk, so everything past the end of
+    // the compressed stream is ignored. Also returns what decompressing had to work around
+    pub fn decompress_entry(
+        &self,
+        data: &[u8],
+        method: CompressionMethod,
+    ) -> Result<(Vec<u8>, Option<Notice>), Error> {
+        let mut out = Vec::with_capacity(self.uncompressed_size as usize);
+
+        match method {
+            CompressionMethod::None => {
+                let len = match self.uncompressed_size as usize {
+                    0 => data.len(),
+                    size => size.min(data.len()),
+                };
+                out.extend_from_slice(&data[..len]);
+            }
+            CompressionMethod::Zlib => {
+                flate2::read::ZlibDecoder::new(data).read_to_end(&mut out)?;
+            }
+            CompressionMethod::Zstd => {
+                zstd::stream::read::Decoder::new(data)?
+                    .single_frame()
+                    .read_to_end(&mut out)?;
+            }
+            CompressionMethod::Lz4 => {
+                return self.decompress_lz4(data);
+            }
+        }
+
+        Ok((out, None))
+    }
+
+    pub fn decompress_lz4(&self, compressed: &[u8]) -> Result<(Vec<u8>, Option<Notice>), Error> {
+        match lz4_flex::decompress(compressed, self.uncompressed_size as usize) {
+            Err(lz4_flex::block::DecompressError::OutputTooSmall { .. }) => {
+                let data = lz4_decompress_undersized(compressed, self.uncompressed_size as usize)?;
+                let notice = Notice::UndersizedLz4 {
+                    file: self.name(),
+                    declared: self.uncompressed_size,
+                    actual: data.len(),
+                };
+                Ok((data, Some(notice)))
+            }
+            result => Ok((result?, None)),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        // A name that fills the whole field has no terminator
+        let size = self
+            .name
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(self.name.len());
+
+        // Names are UTF-8, but a long one can be cut off in the middle of a character
+        String::from_utf8_lossy(&self.name[..size]).into_owned()
+    }
+
+    // 48 bit offset of the data, split into the low 32 and high 16 bits on disk, into the part
+    // file archive_part names (read_entry picks the part, this is only the position in it)
+    pub fn offset(&self) -> u64 {
+        self.offset_in_file_1 as u64 | ((self.offset_in_file_2 as u64) << 32)
+    }
+}
+
+impl Display for PAKFileEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Module {
+    pub description: ModuleDescription,
+    pub dependencies: Vec<ModuleDescription>,
+    // Untouched ModuleInfo node and version element from meta.lsx, kept so the full metadata can be
+    // written back out without losing anything the tool doesn't model
+    pub module_info: Option<kiss_xml::dom::Element>,
+    pub meta_version: Option<kiss_xml::dom::Element>,
+    // Pak the module was read from, and its priority from the pak header. That byte is the only
+    // priority a pak has: the high nibble of the entry flags is the compression level, not a
+    // priority, and never affects the load order
+    pub pak: Option<PathBuf>,
+    pub priority: u8,
+}
+
+impl Module {
+    /// Parse a meta.lsx on its own, for loose mods that aren't packed into a pak
+    ///
+    /// ```
+    /// let meta = r#"<?xml version="1.0" encoding="UTF-8"?>
+    /// <save>
+    ///   <version major="4" minor="0" revision="9" build="328"/>
+    ///   <region id="Config">
+    ///     <node id="root">
+    ///       <children>
+    ///         <node id="Dependencies">
+    ///           <children>
+    ///             <node id="ModuleShortDesc">
+    ///               <attribute id="Folder" type="LSString" value="Library"/>
+    ///               <attribute id="MD5" type="LSString" value=""/>
+    ///               <attribute id="Name" type="LSString" value="Library"/>
+    ///               <attribute id="UUID" type="FixedString" value="00000000-0000-0000-0000-000000000002"/>
+    ///               <attribute id="Version64" type="int64" value="36028797018963968"/>
+    ///             </node>
+    ///           </children>
+    ///         </node>
+    ///         <node id="ModuleInfo">
+    ///           <attribute id="Folder" type="LSString" value="Loose"/>
+    ///           <attribute id="MD5" type="LSString" value=""/>
+    ///           <attribute id="Name" type="LSString" value="Loose Mod"/>
+    ///           <attribute id="UUID" type="FixedString" value="00000000-0000-0000-0000-000000000001"/>
+    ///           <attribute id="Version64" type="int64" value="36169534507319297"/>
+    ///         </node>
+    ///       </children>
+    ///     </node>
+    ///   </region>
+    /// </save>"#;
+    ///
+    /// let module = lsxwriter::Module::from_meta_bytes(meta.as_bytes()).unwrap();
+    /// assert_eq!(module.description.name, "Loose Mod");
+    /// assert_eq!(module.description.display_version(), "1.1.0.1");
+    /// assert_eq!(module.dependencies[0].name, "Library");
+    /// assert!(module.pak.is_none());
+    /// ```
+    pub fn from_meta_bytes(data: &[u8]) -> Result<Module, Error> {
+        let xml_meta = if lsf::is_lsf(data) {
+            lsf::read(data)?
+        } else {
+            parse_lsx(data)?
+        };
+
+        let children = xml_meta
+            .root_element()
+            .first_element_by_name("region")?
+            .first_element_by_name("node")?
+            .first_element_by_name("children")?;
+
+        let mod_info = children
+            .child_elements()
+            .find(|child| child.get_attr("id").is_some_and(|id| id == "ModuleInfo"))
+            .ok_or(PAKError::NoMetadata)?;
+
+        let description = ModuleDescription::parse(mod_info)?;
+
+        // Usually the ModuleShortDescs are the direct children of Dependencies, but some metas wrap
+        // each one in another node (DependencyData), so look for them at any depth
+        let mut dependencies = Vec::new();
+        if let Some(dependencies_node) = children
+            .child_elements()
+            .find(|child| child.get_attr("id").is_some_and(|id| id == "Dependencies"))
+        {
+            Self::find_module_short_descs(dependencies_node, &mut dependencies)?;
+        }
+
+        Ok(Module {
+            description,
+            dependencies,
+            module_info: Some(mod_info.clone()),
+            meta_version: xml_meta
+                .root_element()
+                .first_element_by_name("version")
+                .ok()
+                .cloned(),
+            pak: None,
+            priority: 0,
+        })
+    }
+
+    pub fn find_module_short_descs(
+        element: &kiss_xml::dom::Element,
+        found: &mut Vec<ModuleDescription>,
+    ) -> Result<(), PAKError> {
+        for child in element.child_elements() {
+            if child.name() == "node"
+                && child
+                    .get_attr("id")
+                    .is_some_and(|id| id == "ModuleShortDesc")
+            {
+                found.push(ModuleDescription::parse(child)?);
+            } else {
+                Self::find_module_short_descs(child, found)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Full meta.lsx document for this module (as opposed to the ModuleShortDesc in modsettings)
+    pub fn as_meta_xml(&self) -> kiss_xml::dom::Document {
+        let mut dependencies = kiss_xml::dom::Element::new_from_name("children").unwrap();
+        for dependency in &self.dependencies {
+            dependencies.append(dependency.as_xml());
+        }
+
+        let mut dependencies_node = kiss_xml::dom::Element::new_from_name("node").unwrap();
+        dependencies_node.set_attr("id", "Dependencies").unwrap();
+        dependencies_node.append(dependencies);
+
+        let module_info = self.module_info.clone().unwrap_or_else(|| {
+            let mut module_info = self.description.as_xml();
+            module_info.set_attr("id", "ModuleInfo").unwrap();
+            module_info
+        });
+
+        let mut children = kiss_xml::dom::Element::new_from_name("children").unwrap();
+        children.append(dependencies_node);
+        children.append(module_info);
+
+        let mut root = kiss_xml::dom::Element::new_from_name("node").unwrap();
+        root.set_attr("id", "root").unwrap();
+        root.append(children);
+
+        let mut region = kiss_xml::dom::Element::new_from_name("region").unwrap();
+        region.set_attr("id", "Config").unwrap();
+        region.append(root);
+
+        let version = self.meta_version.clone().unwrap_or_else(|| {
+            kiss_xml::dom::Element::new_with_attributes(
+                "version",
+                HashMap::from([
+                    ("major", "4"),
+                    ("minor", "0"),
+                    ("revision", "9"),
+                    ("build", "331"),
+                ]),
+            )
+            .unwrap()
+        });
+
+        let mut save = kiss_xml::dom::Element::new_from_name("save").unwrap();
+        save.append(version);
+        save.append(region);
+
+        kiss_xml::dom::Document::new_with_decl_dtd(
+            save,
+            Some(
+                kiss_xml::dom::Declaration::from_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#)
+                    .unwrap(),
+            ),
+            None,
+        )
+    }
+}
+
+#[derive(Debug, Default, Eq, Clone)]
+pub struct ModuleDescription {
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub folder: String,
+    pub md5: String,
+    pub name: String,
+    pub publish_handle: Option<String>,
+    // Lowercased, since mods aren't consistent about GUID case and the game doesn't care. The
+    // spelling from the file is kept for --preserve-uuid-case
+    pub uuid: String,
+    pub written_uuid: String,
+    pub version64: String,
+    // Standalone build number some mods track updates with, next to Version64
+    pub build: Option<String>,
+    // Type of each attribute in TYPED_ATTRIBUTES as it was read, by id
+    pub attribute_types: HashMap<String, String>,
+    // Attributes that only reference localized text (a handle without a value), by id
+    pub translated: HashMap<String, TranslatedString>,
+}
+
+// TranslatedString attribute value, which points into the localization files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslatedString {
+    pub handle: String,
+    pub version: Option<String>,
+}
+
+impl Hash for ModuleDescription {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.uuid.hash(state);
+    }
+}
+
+impl PartialEq for ModuleDescription {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid
+    }
+}
+
+impl ModuleDescription {
+    // GustavX as a freshly installed game lists it, for a modsettings.lsx that lost it
+    pub fn base_game() -> Self {
+        let uuid = String::from("cb555efe-2d9e-131f-8195-a89329d218ea");
+        Self {
+            folder: String::from("GustavX"),
+            name: String::from("GustavX"),
+            written_uuid: uuid.clone(),
+            uuid,
+            version64: String::from("36028797018963968"),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(mod_element: &kiss_xml::dom::Element) -> Result<Self, PAKError> {
+        let required = |attr: &'static str| {
+            Self::get_attr(mod_element, attr).ok_or_else(|| PAKError::MissingAttribute {
+                node: mod_element
+                    .get_attr("id")
+                    .cloned()
+                    .unwrap_or_else(|| mod_element.name().to_string()),
+                attr,
+            })
+        };
+
+        let author = Self::get_attr(mod_element, "Author");
+        let description = Self::get_attr(mod_element, "Description");
+        let folder = required("Folder")?;
+        let md5 = required("MD5")?;
+        let name = required("Name")?;
+        // 0 means unpublished, same as no PublishHandle at all (which as_xml writes as 0)
+        let publish_handle =
+            Self::get_attr(mod_element, "PublishHandle").filter(|handle| handle.trim() != "0");
+        let written_uuid = required("UUID")?;
+        let uuid = written_uuid.to_ascii_lowercase();
+        let version64 = required("Version64")?;
+        let build = Self::get_attr(mod_element, "Build");
+
+        let attribute_types = mod_element
+            .child_elements()
+            .filter_map(|att| {
+                let id = att.get_attr("id")?;
+                let ty = att.get_attr("type")?;
+                TYPED_ATTRIBUTES
+                    .contains(&id.as_str())
+                    .then(|| (id.to_string(), ty.to_string()))
+            })
+            .collect();
+
+        let translated = mod_element
+            .child_elements()
+            .filter(|att| att.get_attr("value").is_none())
+            .filter_map(|att| {
+                let id = att.get_attr("id")?;
+                let handle = att.get_attr("handle")?;
+                Some((
+                    id.to_string(),
+                    TranslatedString {
+                        handle: handle.to_string(),
+                        version: att.get_attr("version").cloned(),
+                    },
+                ))
+            })
+            .collect();
+
+        Ok(Self {
+            author,
+            description,
+            folder,
+            md5,
+            name,
+            publish_handle,
+            uuid,
+            written_uuid,
+            version64,
+            build,
+            attribute_types,
+            translated,
+        })
+    }
+
+    pub fn attribute_type<'a>(&'a self, id: &str, default: &'a str) -> &'a str {
+        self.attribute_types.get(id).map_or(default, String::as_str)
+    }
+
+    pub fn version(&self) -> Option<Version> {
+        self.version64.parse().ok()
+    }
+
+    // (major, minor, revision, build), all zero if Version64 isn't a number
+    pub fn decoded_version(&self) -> (u16, u16, u16, u32) {
+        let version = self.version().unwrap_or_default();
+        (
+            version.major,
+            version.minor,
+            version.revision,
+            version.build,
+        )
+    }
+
+    // major.minor.revision.build, or Version64 as written if it isn't a number. version64 itself
+    // is never rewritten, so as_xml keeps the original value
+    pub fn display_version(&self) -> String {
+        self.version()
+            .map_or_else(|| self.version64.clone(), |version| version.to_string())
+    }
+
+    // True if the mod came from the Steam Workshop, which sets PublishHandle to the item's id.
+    // Local mods leave it out or write 0
+    pub fn is_workshop(&self) -> bool {
+        self.publish_handle
+            .as_deref()
+            .and_then(|handle| handle.parse::<u64>().ok())
+            .is_some_and(|handle| handle != 0)
+    }
+
+    // "workshop" or "local", see is_workshop
+    pub fn source(&self) -> &'static str {
+        if self.is_workshop() {
+            "workshop"
+        } else {
+            "local"
+        }
+    }
+
+    // True if this module is part of the installed game rather than a mod
+    pub fn is_base_game(&self) -> bool {
+        self.name.starts_with(BASE_GAME_MOD_PREFIX)
+            || BASE_GAME_MODULES.iter().any(|(folder, uuid)| {
+                self.folder == *folder || self.uuid.eq_ignore_ascii_case(uuid)
+            })
+    }
+
+    pub fn get_attr(mod_element: &kiss_xml::dom::Element, name: &str) -> Option<String> {
+        let att = mod_element
+            .child_elements()
+            .find(|att| match att.get_attr("id") {
+                Some(id) => id == name,
+                None => false,
+            })?;
+
+        if let Some(val) = att.get_attr("value") {
+            let val = val
+                .strip_prefix("<![CDATA[")
+                .and_then(|val| val.strip_suffix("]]>"))
+                .unwrap_or(val);
+
+            return Some(val.to_string());
+        }
+
+        // Long text (usually the description) is sometimes the element's content, wrapped in
+        // CDATA, instead of the value attribute
+        let content: String = att
+            .children()
+            .filter(|child| child.is_text() || child.is_cdata())
+            .map(|child| child.text())
+            .collect();
+
+        (!content.is_empty()).then_some(content)
+    }
+
+    pub fn as_xml(&self) -> kiss_xml::dom::Element {
+        let mut elem = kiss_xml::dom::Element::new_from_name("node").unwrap();
+
+        elem.set_attr("id", "ModuleShortDesc").unwrap();
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "Folder"),
+                    ("type", self.attribute_type("Folder", "LSString")),
+                    ("value", &self.folder),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "MD5"),
+                    ("type", self.attribute_type("MD5", "LSString")),
+                    ("value", &self.md5),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "Name"),
+                    ("type", self.attribute_type("Name", "LSString")),
+                    ("value", &self.name),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        if let Some(build) = &self.build {
+            elem.append(
+                kiss_xml::dom::Element::new(
+                    "attribute",
+                    None,
+                    Some(HashMap::from([
+                        ("id", "Build"),
+                        ("type", self.attribute_type("Build", "int32")),
+                        ("value", build),
+                    ])),
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap(),
+            );
+        }
+
+        let publish_handle_str = if let Some(publish_handle) = &self.publish_handle {
+            publish_handle.clone()
+        } else {
+            String::from("0")
+        };
+
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "PublishHandle"),
+                    ("type", "uint64"),
+                    ("value", &publish_handle_str),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "UUID"),
+                    ("type", "guid"),
+                    ("value", &self.uuid),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        elem.append(
+            kiss_xml::dom::Element::new(
+                "attribute",
+                None,
+                Some(HashMap::from([
+                    ("id", "Version64"),
+                    ("type", "int64"),
+                    ("value", &self.version64),
+                ])),
+                None,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        elem
+    }
+}
+
+// Some packers record an uncompressed size smaller than the real content. Retry with a growing
+// buffer, up to the most an lz4 block can expand to
+fn lz4_decompress_undersized(
+    compressed: &[u8],
+    declared: usize,
+) -> Result<Vec<u8>, lz4_flex::block::DecompressError> {
+    let limit = compressed.len().saturating_mul(255);
+    let mut size = declared.max(64);
+
+    loop {
+        size = size.saturating_mul(2).min(limit);
+
+        let mut data = vec![0; size];
+        match lz4_flex::block::decompress_into(compressed, &mut data) {
+            Ok(len) => {
+                data.truncate(len);
+                return Ok(data);
+            }
+            Err(lz4_flex::block::DecompressError::OutputTooSmall { .. }) if size < limit => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// How far back an lz4 match can reach
+const LZ4_WINDOW: usize = 64 * 1024;
+
+// Entries of a file list (which, unlike the files in it, is always lz4 compressed), decompressed
+// as they are read. Only the last 64 KiB of output, which later matches can copy from, are kept.
+// Each entry is parsed field by field as little endian (see PAKFileEntry::parse), so nothing
+// depends on the layout or byte order of the host
+pub struct EntryReader<'a> {
+    compressed: &'a [u8],
+    position: usize,
+    // Decompressed output, from the oldest byte a match can still reach
+    out: Vec<u8>,
+    // Start of the next entry in out
+    read: usize,
+    remaining: usize,
+    file_count: usize,
+    decompressed: usize,
+}
+
+impl<'a> EntryReader<'a> {
+    fn new(compressed: &'a [u8], file_count: usize) -> Self {
+        Self {
+            compressed,
+            position: 0,
+            out: Vec::new(),
+            read: 0,
+            remaining: file_count,
+            file_count,
+            decompressed: 0,
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, lz4_flex::block::DecompressError> {
+        let byte = *self
+            .compressed
+            .get(self.position)
+            .ok_or(lz4_flex::block::DecompressError::ExpectedAnotherByte)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    // A length of 15 goes on in the following bytes, for as long as they are 255
+    fn length(&mut self, nibble: u8) -> Result<usize, lz4_flex::block::DecompressError> {
+        let mut length = nibble as usize;
+        if nibble == 15 {
+            loop {
+                let byte = self.byte()?;
+                length += byte as usize;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(length)
+    }
+
+    // One lz4 sequence: literals, then a match copied from earlier output, except for the last
+    fn sequence(&mut self) -> Result<(), lz4_flex::block::DecompressError> {
+        let token = self.byte()?;
+
+        let literals = self.length(token >> 4)?;
+        let end = self.position.saturating_add(literals);
+        let literal_bytes = self
+            .compressed
+            .get(self.position..end)
+            .ok_or(lz4_flex::block::DecompressError::LiteralOutOfBounds)?;
+        self.out.extend_from_slice(literal_bytes);
+        self.position = end;
+        self.decompressed += literals;
+
+        if self.position == self.compressed.len() {
+            return Ok(());
+        }
+
+        let offset = u16::from_le_bytes([self.byte()?, self.byte()?]) as usize;
+        let length = self.length(token & 0x0f)? + 4;
+        if offset == 0 || offset > self.out.len() {
+            return Err(lz4_flex::block::DecompressError::OffsetOutOfBounds);
+        }
+
+        // The match can overlap the bytes it produces, so copy one at a time
+        let start = self.out.len() - offset;
+        for i in start..start + length {
+            self.out.push(self.out[i]);
+        }
+        self.decompressed += length;
+
+        Ok(())
+    }
+}
+
+impl Iterator for EntryReader<'_> {
+    type Item = Result<PAKFileEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            // Only left unread when file_count is 0, otherwise the last entry read it all
+            if self.position < self.compressed.len() {
+                return self.finish().err().map(Err);
+            }
+            return None;
+        }
+
+        while self.out.len() - self.read < PAK_FILE_ENTRY_SIZE {
+            let result = if self.position < self.compressed.len() {
+                self.sequence().map_err(Error::from)
+            } else {
+                // A list that doesn't hold file_count entries means a corrupt or unknown layout
+                Err(Error::Pak(PAKError::Corrupt {
+                    expected: PAK_FILE_ENTRY_SIZE * self.file_count,
+                    actual: self.decompressed,
+                }))
+            };
+
+            if let Err(e) = result {
+                self.remaining = 0;
+                return Some(Err(e));
+            }
+        }
+
+        let entry = PAKFileEntry::parse(&self.out[self.read..self.read + PAK_FILE_ENTRY_SIZE]);
+        self.read += PAK_FILE_ENTRY_SIZE;
+        self.remaining -= 1;
+        self.trim();
+
+        if self.remaining == 0
+            && let Err(e) = self.finish()
+        {
+            return Some(Err(e));
+        }
+
+        Some(Ok(entry))
+    }
+}
+
+impl EntryReader<'_> {
+    // Drop what no entry or match needs anymore, now and then so it isn't moved every time
+    fn trim(&mut self) {
+        if self.read > 2 * LZ4_WINDOW {
+            let unused = self.read - LZ4_WINDOW;
+            self.out.drain(..unused);
+            self.read -= unused;
+        }
+    }
+
+    // Decompress whatever follows the last entry. A list that holds more than file_count entries
+    // is as corrupt as one that holds fewer
+    fn finish(&mut self) -> Result<(), Error> {
+        while self.position < self.compressed.len() {
+            if let Err(e) = self.sequence() {
+                self.position = self.compressed.len();
+                return Err(e.into());
+            }
+            self.read = self.out.len();
+            self.trim();
+        }
+
+        let expected = PAK_FILE_ENTRY_SIZE * self.file_count;
+        if self.decompressed != expected {
+            return Err(Error::Pak(PAKError::Corrupt {
+                expected,
+                actual: self.decompressed,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+// data without the UTF-8 byte order mark the game and some editors start files with, and whether
+// it had one
+pub fn strip_bom(data: &[u8]) -> (&[u8], bool) {
+    match data.strip_prefix(BOM.as_bytes()) {
+        Some(rest) => (rest, true),
+        None => (data, false),
+    }
+}
+
+// Parse an LSX document, ignoring the byte order mark if it has one
+pub fn parse_lsx(data: &[u8]) -> Result<kiss_xml::dom::Document, Error> {
+    let mut document = kiss_xml::parse_str(str::from_utf8(strip_bom(data).0)?)?;
+    unescape_attributes(document.root_element_mut());
+    Ok(document)
+}
+
+// kiss_xml keeps attribute values as written, escapes and all, which writing would escape again
+fn unescape_attributes(element: &mut kiss_xml::dom::Element) {
+    let attributes: Vec<(String, String)> = element
+        .attributes()
+        .iter()
+        .map(|(name, value)| (name.clone(), kiss_xml::unescape(value.as_str())))
+        .collect();
+    for (name, value) in attributes {
+        // Names the parser accepted are valid
+        let _ = element.set_attr(name, value);
+    }
+
+    for child in element.child_elements_mut() {
+        unescape_attributes(child);
+    }
+}
+
+// Serialize a document the way kiss_xml does, but formatted like the game and LSLib write it:
+// attributes in their order instead of alphabetically, two space indents, childless elements
+// self-closed and only the characters .NET's XmlWriter escapes in attribute values escaped. That
+// way rewriting a file only changes what was meant to change
+pub fn lsx_string(doc: &kiss_xml::dom::Document) -> String {
+    let mut out = String::new();
+    if let Some(declaration) = doc.declaration() {
+        out.push_str(&format!("{declaration}\n"));
+    }
+    write_lsx_element(&mut out, doc.root_element(), 0);
+    out
+}
+
+// Apostrophes stay as they are (kiss_xml writes &apos;), since the game writes names like "Tav's
+// Hair" that way
+fn lsx_attribute_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            '\t' => escaped.push_str("&#x9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_lsx_element(out: &mut String, element: &kiss_xml::dom::Element, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    let mut attributes: Vec<(&String, &String)> = element.attributes().iter().collect();
+    attributes.sort_by_key(|(name, _)| {
+        (
+            LSX_ATTRIBUTE_ORDER
+                .iter()
+                .position(|known| known == name)
+                .unwrap_or(LSX_ATTRIBUTE_ORDER.len()),
+            name.as_str(),
+        )
+    });
+
+    out.push_str(&format!("{indent}<{}", element.name()));
+    for (name, value) in attributes {
+        out.push_str(&format!(" {name}=\"{}\"", lsx_attribute_escape(value)));
+    }
+
+    let children: Vec<_> = element
+        .children()
+        .filter(|child| !child.is_text() || !child.text().trim().is_empty())
+        .collect();
+
+    if children.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+
+    // Text content stays inline, like <attribute>text</attribute>
+    if children.iter().all(|child| !child.is_element()) {
+        out.push('>');
+        for child in children {
+            out.push_str(&child.to_string_with_indent("  "));
+        }
+        out.push_str(&format!("</{}>\n", element.name()));
+        return;
+    }
+
+    out.push_str(">\n");
+    for child in children {
+        match child.as_element() {
+            Ok(child) => write_lsx_element(out, child, depth + 1),
+            Err(_) => out.push_str(&format!(
+                "{indent}  {}\n",
+                child.to_string_with_indent("  ")
+            )),
+        }
+    }
+    out.push_str(&format!("{indent}</{}>\n", element.name()));
+}
+
+// The regions of a save, which hold everything LSF stores
+pub fn regions(doc: &kiss_xml::dom::Document) -> Vec<String> {
+    doc.root_element()
+        .elements_by_name("region")
+        .map(|region| region.to_string())
+        .collect()
+}
+
+// Parse every pak in the mods directory
+pub fn scan_mods(
+    mods_dir: &Path,
+    options: ScanOptions,
+) -> Result<(Vec<Module>, Vec<Notice>), Error> {
+    let (paks, mut notices) = scan_paks(mods_dir, options)?;
+    let (mods, unreadable) = modules(&paks);
+    notices.extend(unreadable);
+    Ok((mods, notices))
+}
+
+// Metadata of each pak, in the same order. This runs on one thread even when the paks were opened
+// on several, since the parsed XML can't be sent between threads, but open_paks already read the
+// metadata so only parsing it is left. Mods without readable metadata are left out and reported in
+// the notices (along with what reading the paks worked around), paks that aren't mods are left out
+// quietly
+pub fn modules(paks: &[PAKFile]) -> (Vec<Module>, Vec<Notice>) {
+    let mut notices = Vec::new();
+    let mods = paks
+        .iter()
+        .filter(|pak| pak.is_module())
+        .filter_map(|pak| {
+            let module = pak.module();
+            notices.extend(pak.take_notices());
+            module
+                .map_err(|e| {
+                    notices.push(Notice::UnreadableMetadata {
+                        pak: pak.path().to_path_buf(),
+                        error: e.to_string(),
+                    })
+                })
+                .ok()
+        })
+        .collect();
+    (mods, notices)
+}
+
+// Open every pak in the mods directory, skipping ones that can't be read. Those are reported in
+// the notices (see unreadable_paks)
+pub fn scan_paks(
+    mods_dir: &Path,
+    options: ScanOptions,
+) -> Result<(Vec<PAKFile>, Vec<Notice>), Error> {
+    Ok(open_paks(pak_paths(mods_dir)?, options))
+}
+
+// Every .pak file in the mods directory, in no particular order
+pub fn pak_paths(mods_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(fs::read_dir(mods_dir)?
+        .filter_map(|path| {
+            let path = path.ok()?;
+
+            path.file_name()
+                .to_str()
+                .is_some_and(|name| name.ends_with(".pak"))
+                .then(|| path.path())
+        })
+        .collect())
+}
+
+// Pak paths listed one per line in a manifest, instead of found in the mods directory. Relative
+// paths are taken from the manifest's directory
+pub fn read_manifest(manifest: &Path) -> Result<Vec<PathBuf>, Error> {
+    let base = manifest.parent().unwrap_or(Path::new(""));
+
+    Ok(fs::read_to_string(manifest)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+// Priority overrides, one "UUID = priority" per line (which is also valid TOML). Quotes around the
+// UUID are optional, blank lines and lines starting with # are ignored
+pub fn read_overrides(path: &Path) -> Result<HashMap<String, i64>, Error> {
+    let mut overrides = HashMap::new();
+
+    for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || {
+            Error::Overrides(format!(
+                "{}:{}: expected UUID = priority, got \"{line}\"",
+                path.display(),
+                number + 1
+            ))
+        };
+        let (uuid, priority) = line.split_once('=').ok_or_else(invalid)?;
+        let uuid = uuid.trim().trim_matches('"').to_ascii_lowercase();
+        let priority = priority.trim().parse().map_err(|_| invalid())?;
+
+        overrides.insert(uuid, priority);
+    }
+
+    Ok(overrides)
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct ScanOptions<'a> {
+    // Only open this many paks, taken in name order so repeated runs see the same ones
+    pub sample: Option<usize>,
+    // Look for the pak header past the start of the file (see PAKFile::open_with)
+    pub scan_magic: bool,
+    // Threads to open paks and read their metadata on, 0 or 1 to do them one at a time
+    pub jobs: usize,
+    // Where the parts of multi part paks are, if not next to them
+    pub part_dir: Option<&'a Path>,
+    // Treat paks whose file list doesn't match the header MD5 as unreadable
+    pub verify: bool,
+}
+
+// Paks are opened in name order, and with several jobs the results are gathered back into that
+// order before anything is reported, so the output doesn't depend on which thread finished first
+pub fn open_paks(mut paths: Vec<PathBuf>, options: ScanOptions) -> (Vec<PAKFile>, Vec<Notice>) {
+    let mut notices: Vec<Notice> = sample_paths(&mut paths, options.sample)
+        .into_iter()
+        .collect();
+
+    let open = |paths: &[PathBuf]| -> Vec<Result<PAKFile, String>> {
+        paths
+            .iter()
+            .map(|path| {
+                PAKFile::open_lazy(path.clone(), options.scan_magic, options.part_dir)
+                    .and_then(|mut pak| {
+                        if options.verify {
+                            pak.verify_checksum()?;
+                        }
+                        pak.read_ahead();
+                        Ok(pak)
+                    })
+                    .map_err(|e| e.to_string())
+            })
+            .collect()
+    };
+
+    let results = if options.jobs > 1 && paths.len() > 1 {
+        let chunk_size = paths.len().div_ceil(options.jobs);
+        thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || open(chunk)))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    } else {
+        open(&paths)
+    };
+
+    // The data parts of a multi part pak are .pak files too, but not paks of their own
+    let part_paths: HashSet<&Path> = results
+        .iter()
+        .flatten()
+        .flat_map(|pak| pak.parts.iter().skip(1).map(|part| part.path()))
+        .collect();
+    let skip: Vec<bool> = paths
+        .iter()
+        .map(|path| part_paths.contains(path.as_path()))
+        .collect();
+
+    let paks = results
+        .into_iter()
+        .zip(skip)
+        .filter_map(|(result, skip)| match result {
+            Ok(module) => Some(module),
+            Err(_) if skip => None,
+            Err(e) => {
+                notices.push(Notice::UnreadablePak(e));
+                None
+            }
+        })
+        .collect();
+
+    (paks, notices)
+}
+
+fn sample_paths(paths: &mut Vec<PathBuf>, sample: Option<usize>) -> Option<Notice> {
+    paths.sort();
+
+    let total = paths.len();
+    paths.truncate(sample?);
+    Some(Notice::Sampled {
+        scanned: paths.len(),
+        total,
+    })
+}
+
+// Name of the metadata cache in the game data folder
+pub const CACHE_FILE: &str = "lsxwriter-cache.json";
+
+// Bumped whenever the cache layout changes, so caches from older versions are ignored
+const CACHE_VERSION: u32 = 1;
+
+// Metadata of paks read on earlier runs, keyed by pak path. An entry is only used while the pak
+// still has the size and modification time it had when it was read
+#[derive(Debug, Default)]
+pub struct ModuleCache {
+    path: PathBuf,
+    paks: HashMap<PathBuf, CachedPak>,
+    changed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CachedPak {
+    size: u64,
+    modified: u128,
+    priority: u8,
+    // Data parts of a multi part pak, which aren't paks of their own
+    parts: Vec<PathBuf>,
+    // The module's full meta.lsx, or None for paks that aren't mods
+    meta: Option<String>,
+}
+
+impl ModuleCache {
+    // A cache that's missing, unreadable or from another version of the tool starts out empty.
+    // Entries for paks that no longer exist are dropped
+    pub fn open(path: PathBuf) -> Self {
+        let mut cache = Self {
+            path,
+            ..Default::default()
+        };
+
+        let Some(json) = fs::read_to_string(&cache.path)
+            .ok()
+            .and_then(|text| json::parse(&text))
+        else {
+            return cache;
+        };
+        if json.get("version").and_then(json::Value::parse_number) != Some(CACHE_VERSION) {
+            return cache;
+        }
+
+        for pak in json
+            .get("paks")
+            .and_then(json::Value::as_array)
+            .unwrap_or_default()
+        {
+            let entry = || {
+                let path = PathBuf::from(pak.get("path")?.as_str()?);
+                let cached = CachedPak {
+                    size: pak.get("size")?.parse_number()?,
+                    modified: pak.get("modified")?.parse_number()?,
+                    priority: pak.get("priority")?.parse_number()?,
+                    parts: pak
+                        .get("parts")?
+                        .as_array()?
+                        .iter()
+                        .map(|part| part.as_str().map(PathBuf::from))
+                        .collect::<Option<_>>()?,
+                    meta: match pak.get("meta")? {
+                        json::Value::Null => None,
+                        meta => Some(meta.as_str()?.to_string()),
+                    },
+                };
+                Some((path, cached))
+            };
+
+            match entry() {
+                Some((path, cached)) if path.exists() => {
+                    cache.paks.insert(path, cached);
+                }
+                _ => cache.changed = true,
+            }
+        }
+
+        cache
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Whether anything was added or dropped since the cache was opened
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut paths: Vec<&PathBuf> = self.paks.keys().collect();
+        paths.sort();
+
+        let paks: Vec<String> = paths
+            .into_iter()
+            .map(|path| {
+                let pak = &self.paks[path];
+                let parts: Vec<String> = pak
+                    .parts
+                    .iter()
+                    .map(|part| json::string(&part.to_string_lossy()))
+                    .collect();
+                format!(
+                    "    {{\"path\": {}, \"size\": {}, \"modified\": {}, \"priority\": {}, \"parts\": [{}], \"meta\": {}}}",
+                    json::string(&path.to_string_lossy()),
+                    pak.size,
+                    pak.modified,
+                    pak.priority,
+                    parts.join(", "),
+                    pak.meta
+                        .as_deref()
+                        .map_or_else(|| String::from("null"), json::string)
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"version\": {CACHE_VERSION}, \"paks\": [\n{}\n]}}\n",
+            paks.join(",\n")
+        )
+    }
+
+    // Size and modification time of a pak, what a cache entry is checked against
+    fn stamp(path: &Path) -> Option<(u64, u128)> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some((metadata.len(), modified.as_nanos()))
+    }
+
+    // The cached entry of a pak, if the pak hasn't changed since
+    fn get(&self, path: &Path) -> Option<&CachedPak> {
+        let (size, modified) = Self::stamp(path)?;
+        self.paks
+            .get(path)
+            .filter(|cached| cached.size == size && cached.modified == modified)
+    }
+
+    fn insert(&mut self, pak: &PAKFile, module: Option<&Module>) {
+        let Some((size, modified)) = Self::stamp(pak.path()) else {
+            return;
+        };
+
+        let cached = CachedPak {
+            size,
+            modified,
+            priority: pak.header.priority,
+            parts: pak
+                .parts
+                .iter()
+                .skip(1)
+                .map(|part| part.path().to_path_buf())
+                .collect(),
+            meta: module.map(|module| lsx_string(&module.as_meta_xml())),
+        };
+        if self.paks.get(pak.path()) != Some(&cached) {
+            self.paks.insert(pak.path().to_path_buf(), cached);
+            self.changed = true;
+        }
+    }
+}
+
+// Metadata of each pak, like modules(&open_paks(paths, options).0), except that paks the cache
+// has an up to date entry for aren't opened at all, and the paks that are opened are added to it.
+// Also returns how many paks couldn't be read
+pub fn open_modules(
+    mut paths: Vec<PathBuf>,
+    options: ScanOptions,
+    mut cache: Option<&mut ModuleCache>,
+) -> (Vec<Module>, Vec<Notice>) {
+    let sampled = sample_paths(&mut paths, options.sample);
+
+    // Verifying a pak means reading it, which is what the cache saves
+    let mut cached = Vec::new();
+    let mut uncached = Vec::new();
+    for path in paths {
+        let hit = cache
+            .as_deref()
+            .filter(|_| !options.verify)
+            .and_then(|cache| cache.get(&path))
+            .and_then(|pak| match &pak.meta {
+                None => Some((None, &pak.parts)),
+                Some(meta) => Module::from_meta_bytes(meta.as_bytes())
+                    .ok()
+                    .map(|module| (Some((module, pak.priority)), &pak.parts)),
+            });
+
+        match hit {
+            Some((module, parts)) => cached.push((path, module, parts.clone())),
+            None => uncached.push(path),
+        }
+    }
+
+    let parts: HashSet<&Path> = cached
+        .iter()
+        .flat_map(|(_, _, parts)| parts.iter().map(PathBuf::as_path))
+        .collect();
+    uncached.retain(|path| !parts.contains(path.as_path()));
+
+    let (paks, mut notices) = open_paks(
+        uncached,
+        ScanOptions {
+            sample: None,
+            ..options
+        },
+    );
+
+    let mut mods: Vec<Module> = cached
+        .into_iter()
+        .filter_map(|(path, module, _)| {
+            let (module, priority) = module?;
+            Some(Module {
+                pak: Some(path),
+                priority,
+                ..module
+            })
+        })
+        .collect();
+
+    for pak in &paks {
+        if !pak.is_module() {
+            if let Some(cache) = cache.as_deref_mut() {
+                cache.insert(pak, None);
+            }
+            continue;
+        }
+
+        let module = pak.module();
+        notices.extend(pak.take_notices());
+        match module {
+            Ok(module) => {
+                if let Some(cache) = cache.as_deref_mut() {
+                    cache.insert(pak, Some(&module));
+                }
+                mods.push(module);
+            }
+            Err(e) => notices.push(Notice::UnreadableMetadata {
+                pak: pak.path().to_path_buf(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    notices.splice(0..0, sampled);
+    mods.sort_by(|a, b| a.pak.cmp(&b.pak));
+    (mods, notices)
+}
+
+// Profile the game uses unless the player made others
+pub const DEFAULT_PROFILE: &str = "Public";
+
+// modsettings.lsx in the player profile (see DEFAULT_PROFILE). If there's no file by exactly that
+// name, one with the name cased differently (which only matters on case sensitive filesystems) is
+// used instead, callers can tell by the file name of the path returned
+pub fn modsettings_path(game_data: &Path, profile: &str) -> Result<PathBuf, Error> {
+    let profiles = game_data.join("PlayerProfiles");
+    let public = profiles.join(profile);
+    if !public.is_dir() {
+        let mut available: Vec<String> = fs::read_dir(&profiles)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|entry| entry.path().is_dir())
+                    .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        available.sort();
+
+        return Err(Error::NoSuchProfile {
+            profile: profile.to_string(),
+            available,
+        });
+    }
+
+    let path = public.join("modsettings.lsx");
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let variant = fs::read_dir(&public).ok().and_then(|entries| {
+        entries.filter_map(Result::ok).find(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.to_lowercase() == "modsettings.lsx")
+        })
+    });
+
+    // Let opening it report the missing file
+    Ok(variant.map_or(path, |entry| entry.path()))
+}
+
+// The children of the "Mods" node in modsettings.lsx. With repair, any missing node on the way
+// there is created instead of being an error, and added to repaired
+pub fn modlist_mut<'a>(
+    modsettings: &'a mut kiss_xml::dom::Document,
+    repair: bool,
+    repaired: &mut Vec<Notice>,
+) -> Result<&'a mut kiss_xml::dom::Element, ModSettingsError> {
+    let region = child_element_mut(
+        modsettings.root_element_mut(),
+        ("region", None, "ModuleSettings"),
+        repair,
+        repaired,
+    )?;
+    let root = child_element_mut(region, ("node", None, "root"), repair, repaired)?;
+    let root_children = child_element_mut(root, ("children", None, "root"), repair, repaired)?;
+    let mods = child_element_mut(
+        root_children,
+        ("node", Some("Mods"), "Mods"),
+        repair,
+        repaired,
+    )?;
+    child_element_mut(mods, ("children", None, "Mods"), repair, repaired)
+}
+
+// First child element with the given name (and id if given), created if missing and repairing
+fn child_element_mut<'a>(
+    parent: &'a mut kiss_xml::dom::Element,
+    (name, id, id_for_new): (&str, Option<&str>, &str),
+    repair: bool,
+    repaired: &mut Vec<Notice>,
+) -> Result<&'a mut kiss_xml::dom::Element, ModSettingsError> {
+    let position = parent.child_elements().position(|child| {
+        child.name() == name && id.is_none_or(|id| child.get_attr("id").is_some_and(|i| i == id))
+    });
+
+    let position = match position {
+        Some(position) => position,
+        None if repair => {
+            let mut elem = kiss_xml::dom::Element::new_from_name(name).unwrap();
+            if name != "children" {
+                elem.set_attr("id", id_for_new).unwrap();
+            }
+            parent.append(elem);
+            repaired.push(Notice::RepairedNode {
+                name: name.to_string(),
+                id: id_for_new.to_string(),
+            });
+            parent.child_elements().count() - 1
+        }
+        None => {
+            return Err(ModSettingsError::MissingNode(format!(
+                "{name} ({id_for_new}) inside {}",
+                parent.name()
+            )));
+        }
+    };
+
+    Ok(parent.child_elements_mut().nth(position).unwrap())
+}
+
+// Mods currently listed in modsettings.lsx, in order, and the entries that couldn't be read
+pub fn read_modlist(modlist: &kiss_xml::dom::Element) -> (Vec<ModuleDescription>, Vec<Notice>) {
+    let mut skipped = Vec::new();
+    let mods = modlist
+        .child_elements()
+        .filter_map(|element| match ModuleDescription::parse(element) {
+            Ok(module) => Some(module),
+            Err(e) => {
+                skipped.push(Notice::SkippedListedMod(e.to_string()));
+                None
+            }
+        })
+        .collect();
+    (mods, skipped)
+}
+
+// Something the library worked around or decided on its own. The library never prints, so these
+// are returned to the caller to report (or not)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notice {
+    // modsettings.lsx
+    AddedBaseModule {
+        name: String,
+        version: String,
+    },
+    RepairedNode {
+        name: String,
+        id: String,
+    },
+    SkippedListedMod(String),
+    SeveralBaseModules {
+        names: Vec<String>,
+        used: String,
+    },
+    // Paks
+    StoredDespiteFlags(String),
+    UndersizedLz4 {
+        file: String,
+        declared: u32,
+        actual: usize,
+    },
+    UnreadablePak(String),
+    UnreadableMetadata {
+        pak: PathBuf,
+        error: String,
+    },
+    Sampled {
+        scanned: usize,
+        total: usize,
+    },
+    // Order files
+    RepeatedInOrderFile(String),
+    NotInstalled(String),
+}
+
+impl Display for Notice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddedBaseModule { name, version } => write!(
+                f,
+                "Warning: modsettings.lsx has no base game module, adding {name} ({version})"
+            ),
+            Self::RepairedNode { name, id } => {
+                write!(f, "Repaired missing {name} node ({id}) in modsettings")
+            }
+            Self::SkippedListedMod(error) => {
+                write!(f, "Skipping a mod in modsettings.lsx: {error}")
+            }
+            Self::SeveralBaseModules { names, used } => write!(
+                f,
+                "Warning: modsettings.lsx lists several base game modules ({}), using {used}",
+                names.join(", ")
+            ),
+            Self::StoredDespiteFlags(file) => write!(
+                f,
+                "{file}: flagged as compressed but stored uncompressed, reading it as is"
+            ),
+            Self::UndersizedLz4 {
+                file,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "{file}: declared uncompressed size is {declared} bytes, but it decompresses to {actual} bytes"
+            ),
+            Self::UnreadablePak(error) => write!(f, "Failed to parse pak file: {error}"),
+            Self::UnreadableMetadata { pak, error } => write!(
+                f,
+                "Failed to read the metadata of {}: {error}",
+                pak.display()
+            ),
+            Self::Sampled { scanned, total } => write!(
+                f,
+                "Partial results: scanning only {scanned} of {total} paks (--sample)"
+            ),
+            Self::RepeatedInOrderFile(uuid) => {
+                write!(f, "{uuid} is listed more than once in the order file")
+            }
+            Self::NotInstalled(uuid) => {
+                write!(f, "Ignoring {uuid} from the order file (not installed)")
+            }
+        }
+    }
+}
+
+// How many paks the notices say couldn't be opened, the ones left out of the results
+pub fn unreadable_paks(notices: &[Notice]) -> usize {
+    notices
+        .iter()
+        .filter(|notice| matches!(notice, Notice::UnreadablePak(_)))
+        .count()
+}
+
+// A UUID more than one installed pak has, with the mod dedup_mods kept and one it left out
+#[derive(Debug)]
+pub struct DuplicateMod {
+    pub kept: ModuleDescription,
+    pub kept_pak: Option<PathBuf>,
+    pub ignored: ModuleDescription,
+    pub ignored_pak: Option<PathBuf>,
+}
+
+impl Display for DuplicateMod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pak = |pak: &Option<PathBuf>| {
+            pak.as_ref()
+                .map_or(String::new(), |pak| pak.display().to_string())
+        };
+        write!(
+            f,
+            "Duplicate UUID {}: using {} {} from {}, ignoring {} from {}",
+            self.kept.uuid,
+            self.kept.name,
+            self.kept.version64,
+            pak(&self.kept_pak),
+            self.ignored.version64,
+            pak(&self.ignored_pak)
+        )
+    }
+}
+
+// Keep one mod per UUID when several installed paks share it, chosen by the version policy. Ties
+// go to the pak that sorts first by path. Also returns every mod left out that way
+pub fn dedup_mods(
+    mut mods: Vec<Module>,
+    policy: VersionPolicy,
+    existing: &[ModuleDescription],
+) -> (Vec<Module>, Vec<DuplicateMod>) {
+    mods.sort_by(|a, b| a.pak.cmp(&b.pak));
+
+    let mut kept: Vec<Module> = Vec::new();
+    let mut duplicates = Vec::new();
+    for module in mods {
+        let Some(index) = kept
+            .iter()
+            .position(|other| other.description == module.description)
+        else {
+            kept.push(module);
+            continue;
+        };
+
+        let version = module.description.version();
+        let kept_version = kept[index].description.version();
+        let existing_version = existing
+            .iter()
+            .find(|listed| **listed == module.description)
+            .and_then(ModuleDescription::version);
+
+        let replace = match (policy, existing_version) {
+            (VersionPolicy::Lowest, _) => version < kept_version,
+            // When no copy has the listed version, fall back to the highest like below
+            (VersionPolicy::Existing, Some(existing_version))
+                if kept_version == Some(existing_version) =>
+            {
+                false
+            }
+            (VersionPolicy::Existing, Some(existing_version))
+                if version == Some(existing_version) =>
+            {
+                true
+            }
+            // Nothing to keep when modsettings doesn't list it yet
+            (VersionPolicy::Highest | VersionPolicy::Existing, _) => version > kept_version,
+        };
+
+        let loser = if replace {
+            std::mem::replace(&mut kept[index], module)
+        } else {
+            module
+        };
+
+        duplicates.push(DuplicateMod {
+            kept: kept[index].description.clone(),
+            kept_pak: kept[index].pak.clone(),
+            ignored: loser.description,
+            ignored_pak: loser.pak,
+        });
+    }
+
+    (kept, duplicates)
+}
+
+// Pairs of mods (by UUID) whose paks hold files at the same paths, which the game takes from only
+// one of them depending on the load order. Paks that aren't mods, or that share a UUID (versions
+// of the same mod), are never paired. Reads the whole file list of every pak
+pub fn detect_conflicts(paks: &[PAKFile]) -> Vec<(String, String, Vec<String>)> {
+    let mods: Vec<(String, &PAKFile)> = paks
+        .iter()
+        .filter(|pak| pak.is_module())
+        .filter_map(|pak| Some((pak.module().ok()?.description.uuid, pak)))
+        .collect();
+
+    // Mods holding each file, by index into mods
+    let mut owners: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, (_, pak)) in mods.iter().enumerate() {
+        for name in pak.file_names() {
+            owners.entry(name).or_default().push(index);
+        }
+    }
+
+    let mut shared: HashMap<(usize, usize), Vec<String>> = HashMap::new();
+    for (name, indices) in owners {
+        for (i, &a) in indices.iter().enumerate() {
+            for &b in &indices[i + 1..] {
+                if mods[a].0 != mods[b].0 {
+                    shared.entry((a, b)).or_default().push(name.clone());
+                }
+            }
+        }
+    }
+
+    let mut conflicts: Vec<((usize, usize), Vec<String>)> = shared.into_iter().collect();
+    conflicts.sort_by_key(|(pair, _)| *pair);
+    conflicts
+        .into_iter()
+        .map(|((a, b), mut names)| {
+            names.sort();
+            (mods[a].0.clone(), mods[b].0.clone(), names)
+        })
+        .collect()
+}
+
+// Union of the mods of several mods directories, where a mod from a later directory replaces one
+// with the same UUID from an earlier directory. Also returns each replacement
+pub fn overlay_mods(layers: Vec<Vec<Module>>) -> (Vec<Module>, Vec<DuplicateMod>) {
+    let mut kept: Vec<Module> = Vec::new();
+    let mut replaced = Vec::new();
+    for module in layers.into_iter().flatten() {
+        let Some(index) = kept
+            .iter()
+            .position(|other| other.description == module.description)
+        else {
+            kept.push(module);
+            continue;
+        };
+
+        let ignored = std::mem::replace(&mut kept[index], module);
+        replaced.push(DuplicateMod {
+            kept: kept[index].description.clone(),
+            kept_pak: kept[index].pak.clone(),
+            ignored: ignored.description,
+            ignored_pak: ignored.pak,
+        });
+    }
+
+    (kept, replaced)
+}
+
+pub fn find_base_module(
+    modlist: &[ModuleDescription],
+) -> Result<ModuleDescription, ModSettingsError> {
+    // The actual game is a mod that starts with gustav (GustavX or GustavDev. Difference is???)
+    let mut candidates: Vec<ModuleDescription> = modlist
+        .iter()
+        .filter(|old_mod| old_mod.name.starts_with(BASE_GAME_MOD_PREFIX))
+        .cloned()
+        .collect();
+
+    // A list carried over from an older patch can still hold the previous base module. The one
+    // the installed game wrote is the newest, so prefer it (and the earlier entry on a tie)
+    let index = (0..candidates.len())
+        .rev()
+        .max_by_key(|index| candidates[*index].version())
+        .ok_or(ModSettingsError::NoBaseModule)?;
+
+    Ok(candidates.swap_remove(index))
+}
+
+// Problems that don't stop a load order from being written, unless --strict is given
+#[derive(Debug)]
+pub enum Warning {
+    NewerBaseRequired {
+        module: String,
+        module_uuid: String,
+        required: Version,
+        installed: Version,
+    },
+    DuplicateAttribute {
+        module: String,
+        module_uuid: String,
+        id: String,
+        count: usize,
+    },
+    MissingDependency {
+        module: String,
+        module_uuid: String,
+        dependency: String,
+        dependency_uuid: String,
+    },
+    OverrideConflict {
+        module: String,
+        module_uuid: String,
+        module_priority: i64,
+        dependency: String,
+        dependency_priority: i64,
+    },
+    InvalidUuid {
+        module: String,
+        uuid: String,
+    },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NewerBaseRequired {
+                module,
+                required,
+                installed,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{module} requires base game version {required} or newer, but {installed} is installed"
+                )
+            }
+            Self::DuplicateAttribute {
+                module, id, count, ..
+            } => {
+                write!(
+                    f,
+                    "{module}'s ModuleInfo has {count} attributes with id \"{id}\", only the first is used"
+                )
+            }
+            Self::MissingDependency {
+                module,
+                dependency,
+                dependency_uuid,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{module} depends on {dependency} ({dependency_uuid}), which is not installed"
+                )
+            }
+            Self::OverrideConflict {
+                module,
+                module_priority,
+                dependency,
+                dependency_priority,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{dependency} is pinned to {dependency_priority} and {module} to {module_priority}, \
+                    but {module} depends on {dependency} so it still loads after it. Dependencies always \
+                    come first, overrides only order mods within the same dependency layer (higher loads later)"
+                )
+            }
+            Self::InvalidUuid { module, uuid } => {
+                write!(
+                    f,
+                    "{module} has the malformed UUID \"{uuid}\" (expected 8-4-4-4-12 hex digits), the game may ignore it"
+                )
+            }
+        }
+    }
+}
+
+impl Warning {
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            Self::NewerBaseRequired { .. } => "newer-base-required",
+            Self::DuplicateAttribute { .. } => "duplicate-attribute",
+            Self::MissingDependency { .. } => "missing-dependency",
+            Self::OverrideConflict { .. } => "override-conflict",
+            Self::InvalidUuid { .. } => "invalid-uuid",
+        }
+    }
+
+    // Name of the mod the warning is about
+    pub fn module(&self) -> Option<&str> {
+        match self {
+            Self::NewerBaseRequired { module, .. }
+            | Self::DuplicateAttribute { module, .. }
+            | Self::MissingDependency { module, .. }
+            | Self::OverrideConflict { module, .. }
+            | Self::InvalidUuid { module, .. } => Some(module),
+        }
+    }
+
+    // UUID of the mod at fault, which unlike its name can't be shared with another mod. For
+    // InvalidUuid it's as written in meta.lsx
+    pub fn module_uuid(&self) -> Option<&str> {
+        match self {
+            Self::NewerBaseRequired { module_uuid, .. }
+            | Self::DuplicateAttribute { module_uuid, .. }
+            | Self::MissingDependency { module_uuid, .. }
+            | Self::OverrideConflict { module_uuid, .. }
+            | Self::InvalidUuid {
+                uuid: module_uuid, ..
+            } => Some(module_uuid),
+        }
+    }
+}
+
+// Mods whose dependency on the installed base module asks for a newer version of it
+pub fn base_version_warnings(mods: &[Module], base_mod: &ModuleDescription) -> Vec<Warning> {
+    let Some(installed) = base_mod.version() else {
+        return Vec::new();
+    };
+
+    mods.iter()
+        .filter_map(|module| {
+            let required = module
+                .dependencies
+                .iter()
+                .filter(|dependency| *dependency == base_mod)
+                .filter_map(ModuleDescription::version)
+                .max()?;
+
+            (required > installed).then(|| Warning::NewerBaseRequired {
+                module: module.description.name.clone(),
+                module_uuid: module.description.uuid.clone(),
+                required,
+                installed,
+            })
+        })
+        .collect()
+}
+
+// Mods whose UUID isn't a GUID in the canonical format, as written in their meta.lsx
+pub fn invalid_uuid_warnings(mods: &[Module]) -> Vec<Warning> {
+    mods.iter()
+        .filter(|module| !is_valid_guid(&module.description.uuid))
+        .map(|module| Warning::InvalidUuid {
+            module: module.description.name.clone(),
+            uuid: module.description.written_uuid.clone(),
+        })
+        .collect()
+}
+
+// Attribute ids that appear more than once in a mod's ModuleInfo. Lookups take the first, other
+// tools may not
+pub fn duplicate_attribute_warnings(mods: &[Module]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for module in mods {
+        let Some(module_info) = &module.module_info else {
+            continue;
+        };
+
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for id in module_info
+            .elements_by_name("attribute")
+            .filter_map(|attribute| attribute.get_attr("id"))
+        {
+            match counts.iter_mut().find(|(seen, _)| seen == id) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((id.to_string(), 1)),
+            }
+        }
+
+        warnings.extend(
+            counts
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(id, count)| Warning::DuplicateAttribute {
+                    module: module.description.name.clone(),
+                    module_uuid: module.description.uuid.clone(),
+                    id,
+                    count,
+                }),
+        );
+    }
+    warnings
+}
+
+// Why a declared dependency was left out of the dependency graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipReason {
+    BaseGame,
+    SelfDependency,
+    Missing,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BaseGame => write!(f, "base game, always loaded first"),
+            Self::SelfDependency => write!(f, "the mod depends on itself"),
+            Self::Missing => write!(f, "not installed"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SkippedDependency {
+    pub module: ModuleDescription,
+    pub dependency: ModuleDescription,
+    pub reason: SkipReason,
+}
+
+impl Display for SkippedDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Skipped dependency of {} on {} ({}): {}",
+            self.module.name, self.dependency.name, self.dependency.uuid, self.reason
+        )
+    }
+}
+
+// Graph of installed mods, with every declared dependency that was left out and why
+fn dependency_graph(
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+) -> Result<
+    (
+        AcyclicDependencyGraph<ModuleDescription>,
+        Vec<SkippedDependency>,
+    ),
+    Error,
+> {
+    let installed: HashSet<&ModuleDescription> =
+        mods.iter().map(|module| &module.description).collect();
+
+    let mut dep_graph = AcyclicDependencyGraph::new();
+    let mut skipped = Vec::new();
+    for module in mods {
+        dep_graph
+            .depend_on(module.description.clone(), base_mod.clone())
+            .unwrap();
+        for dependency in &module.dependencies {
+            let reason = if dependency.is_base_game() || dependency == base_mod {
+                SkipReason::BaseGame
+            } else if *dependency == module.description {
+                SkipReason::SelfDependency
+            } else if !installed.contains(dependency) {
+                SkipReason::Missing
+            } else {
+                dep_graph
+                    .depend_on(module.description.clone(), dependency.clone())
+                    .map_err(|_| Error::DependencyCycle {
+                        module: format!(
+                            "{} ({})",
+                            module.description.name, module.description.uuid
+                        ),
+                        dependency: format!("{} ({})", dependency.name, dependency.uuid),
+                    })?;
+                continue;
+            };
+
+            skipped.push(SkippedDependency {
+                module: module.description.clone(),
+                dependency: dependency.clone(),
+                reason,
+            });
+        }
+    }
+
+    Ok((dep_graph, skipped))
+}
+
+// How to order the mods that dependencies leave free to go in any order
+#[derive(Clone, Copy, Default)]
+pub struct ResolveOptions<'a> {
+    // Existing load order whose mods keep their relative order (see --preserve-order)
+    pub previous: &'a [ModuleDescription],
+    // Priority overrides by lowercase UUID, higher loads later, unlisted mods count as 0
+    pub overrides: Option<&'a HashMap<String, i64>>,
+}
+
+impl ResolveOptions<'_> {
+    fn override_priority(&self, module: &ModuleDescription) -> i64 {
+        self.overrides
+            .and_then(|overrides| overrides.get(&module.uuid.to_ascii_lowercase()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+// Load order with every module after all of its dependencies. Mods within a layer don't depend on
+// each other, so they are ordered by their priority override first (lowest first), then mods
+// listed in the previous order keep their order from it, ahead of the rest. Those are ordered by
+// pak header priority (lowest first, so higher priority paks load later), then name, then UUID to
+// keep the output stable
+pub fn resolve_order(
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+    options: ResolveOptions,
+) -> Result<(Vec<ModuleDescription>, Vec<SkippedDependency>), Error> {
+    let priorities: HashMap<&str, u8> = mods
+        .iter()
+        .map(|module| (module.description.uuid.as_str(), module.priority))
+        .collect();
+    let previous: HashMap<&str, usize> = options
+        .previous
+        .iter()
+        .enumerate()
+        .map(|(index, module)| (module.uuid.as_str(), index))
+        .collect();
+
+    let (dep_graph, skipped) = dependency_graph(mods, base_mod)?;
+
+    // The base game only gets into the graph as something a mod depends on, so with no mods the
+    // layers are empty and it has to be put in by hand
+    if mods.is_empty() {
+        return Ok((vec![base_mod.clone()], skipped));
+    }
+
+    let order = dep_graph
+        .get_forward_dependency_topological_layers()
+        .into_iter()
+        .flat_map(|layer| {
+            let mut layer: Vec<ModuleDescription> = layer.into_iter().collect();
+            layer.sort_by(|a, b| {
+                let priority = |module: &ModuleDescription| {
+                    priorities.get(module.uuid.as_str()).copied().unwrap_or(0)
+                };
+                let previous_index = |module: &ModuleDescription| {
+                    previous
+                        .get(module.uuid.as_str())
+                        .copied()
+                        .unwrap_or(usize::MAX)
+                };
+                options
+                    .override_priority(a)
+                    .cmp(&options.override_priority(b))
+                    .then_with(|| previous_index(a).cmp(&previous_index(b)))
+                    .then_with(|| priority(a).cmp(&priority(b)))
+                    .then_with(|| a.name.cmp(&b.name))
+                    .then_with(|| a.uuid.cmp(&b.uuid))
+            });
+            layer
+        })
+        .collect();
+
+    Ok((order, skipped))
+}
+
+/// An entry in the resolved load order, with the warnings about that mod so a UI can show them
+/// next to it
+///
+/// ```
+/// use lsxwriter::{Module, ModuleDescription, ResolveOptions, Warning, resolve};
+///
+/// let description = |name: &str, uuid: &str| ModuleDescription {
+///     folder: name.to_string(),
+///     name: name.to_string(),
+///     uuid: uuid.to_string(),
+///     written_uuid: uuid.to_string(),
+///     version64: String::from("36028797018963968"),
+///     ..Default::default()
+/// };
+/// let library = description("Library", "00000000-0000-0000-0000-000000000002");
+/// let mods = [Module {
+///     description: description("Needs Library", "00000000-0000-0000-0000-000000000001"),
+///     dependencies: vec![library],
+///     ..Default::default()
+/// }];
+///
+/// let base = ModuleDescription::base_game();
+/// let (order, _) = resolve(&mods, &base, ResolveOptions::default()).unwrap();
+/// assert!(order[0].warnings.is_empty());
+/// assert_eq!(order[1].load_index, 1);
+/// assert!(matches!(
+///     &order[1].warnings[..],
+///     [Warning::MissingDependency { dependency, .. }] if dependency == "Library"
+/// ));
+/// ```
+pub struct ResolvedMod {
+    pub description: ModuleDescription,
+    pub load_index: usize,
+    pub warnings: Vec<Warning>,
+}
+
+// resolve_order, with each mod's warnings attached to it
+pub fn resolve(
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+    options: ResolveOptions,
+) -> Result<(Vec<ResolvedMod>, Vec<SkippedDependency>), Error> {
+    let (order, skipped) = resolve_order(mods, base_mod, options)?;
+
+    let resolved = order
+        .into_iter()
+        .enumerate()
+        .map(|(load_index, description)| {
+            let mut warnings = Vec::new();
+
+            if let Some(module) = mods.iter().find(|module| module.description == description) {
+                let module = std::slice::from_ref(module);
+                warnings.extend(invalid_uuid_warnings(module));
+                warnings.extend(duplicate_attribute_warnings(module));
+                warnings.extend(base_version_warnings(module, base_mod));
+
+                // A dependency pinned later than its dependent can't follow the pin
+                let module_priority = options.override_priority(&description);
+                warnings.extend(module[0].dependencies.iter().filter_map(|dependency| {
+                    let dependency_priority = options.override_priority(dependency);
+                    (dependency_priority > module_priority).then(|| Warning::OverrideConflict {
+                        module: description.name.clone(),
+                        module_uuid: description.uuid.clone(),
+                        module_priority,
+                        dependency: dependency.name.clone(),
+                        dependency_priority,
+                    })
+                }));
+            }
+
+            warnings.extend(
+                skipped
+                    .iter()
+                    .filter(|skip| skip.reason == SkipReason::Missing && skip.module == description)
+                    .map(|skip| Warning::MissingDependency {
+                        module: description.name.clone(),
+                        module_uuid: description.uuid.clone(),
+                        dependency: skip.dependency.name.clone(),
+                        dependency_uuid: skip.dependency.uuid.clone(),
+                    }),
+            );
+
+            ResolvedMod {
+                description,
+                load_index,
+                warnings,
+            }
+        })
+        .collect();
+
+    Ok((resolved, skipped))
+}
+
+// Reorder to follow the UUIDs listed in an order file (base game first, unlisted mods after the
+// listed ones), rejecting the result if it loads anything before one of its dependencies
+pub fn apply_order_file(
+    order: Vec<ModuleDescription>,
+    order_file: &Path,
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+) -> Result<(Vec<ModuleDescription>, Vec<Notice>), Error> {
+    let mut explicit = Vec::new();
+    let mut notices = Vec::new();
+
+    for line in fs::read_to_string(order_file)?.lines() {
+        let uuid = line.trim();
+        if uuid.is_empty() || uuid.starts_with('#') {
+            continue;
+        }
+
+        match order
+            .iter()
+            .find(|module| module.uuid.eq_ignore_ascii_case(uuid))
+        {
+            Some(module) if module == base_mod => {}
+            Some(module) if !explicit.contains(module) => explicit.push(module.clone()),
+            Some(_) => notices.push(Notice::RepeatedInOrderFile(uuid.to_string())),
+            None => notices.push(Notice::NotInstalled(uuid.to_string())),
+        }
+    }
+
+    let mut new_order = vec![base_mod.clone()];
+    new_order.extend(explicit.iter().cloned());
+    new_order.extend(
+        order
+            .into_iter()
+            .filter(|module| module != base_mod && !explicit.contains(module)),
+    );
+
+    let broken: Vec<String> = check_load_order(&new_order, mods, base_mod)
+        .into_iter()
+        .filter(|violation| {
+            matches!(
+                violation,
+                ContractViolation::DependencyAfterDependent { .. }
+            )
+        })
+        .map(|violation| violation.to_string())
+        .collect();
+
+    if !broken.is_empty() {
+        return Err(Error::OrderFile(format!(
+            "The order file breaks dependency constraints:\n{}",
+            broken.join("\n")
+        )));
+    }
+
+    Ok((new_order, notices))
+}
+
+// A way a load order breaks the rules the game applies when reading modsettings.lsx
+#[derive(Debug)]
+pub enum ContractViolation {
+    BaseNotFirst(String),
+    DuplicateUuid(String),
+    MissingDependency {
+        module: String,
+        module_uuid: String,
+        dependency: String,
+    },
+    DependencyAfterDependent {
+        module: String,
+        module_uuid: String,
+        dependency: String,
+    },
+    InvalidUuid {
+        module: String,
+        uuid: String,
+    },
+    InvalidVersion {
+        module: String,
+        module_uuid: String,
+        version: String,
+    },
+    InvalidPublishHandle {
+        module: String,
+        module_uuid: String,
+        publish_handle: String,
+    },
+}
+
+impl Display for ContractViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BaseNotFirst(first) => {
+                write!(f, "The base game must load first, but {first} does")
+            }
+            Self::DuplicateUuid(uuid) => {
+                write!(f, "UUID {uuid} appears more than once")
+            }
+            Self::MissingDependency {
+                module, dependency, ..
+            } => {
+                write!(
+                    f,
+                    "{module} depends on {dependency}, which is not installed"
+                )
+            }
+            Self::DependencyAfterDependent {
+                module, dependency, ..
+            } => {
+                write!(f, "{module} loads before its dependency {dependency}")
+            }
+            Self::InvalidUuid { module, uuid } => {
+                write!(f, "{module} has an invalid UUID: {uuid}")
+            }
+            Self::InvalidVersion {
+                module, version, ..
+            } => {
+                write!(f, "{module} has an invalid Version64 (int64): {version}")
+            }
+            Self::InvalidPublishHandle {
+                module,
+                publish_handle,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{module} has an invalid PublishHandle (uint64): {publish_handle}"
+                )
+            }
+        }
+    }
+}
+
+impl ContractViolation {
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            Self::BaseNotFirst(_) => "base-not-first",
+            Self::DuplicateUuid(_) => "duplicate-uuid",
+            Self::MissingDependency { .. } => "missing-dependency",
+            Self::DependencyAfterDependent { .. } => "dependency-after-dependent",
+            Self::InvalidUuid { .. } => "invalid-uuid",
+            Self::InvalidVersion { .. } => "invalid-version",
+            Self::InvalidPublishHandle { .. } => "invalid-publish-handle",
+        }
+    }
+
+    // Name of the mod at fault, if it's down to a single mod
+    pub fn module(&self) -> Option<&str> {
+        match self {
+            Self::BaseNotFirst(_) | Self::DuplicateUuid(_) => None,
+            Self::MissingDependency { module, .. }
+            | Self::DependencyAfterDependent { module, .. }
+            | Self::InvalidUuid { module, .. }
+            | Self::InvalidVersion { module, .. }
+            | Self::InvalidPublishHandle { module, .. } => Some(module),
+        }
+    }
+
+    // UUID of the mod at fault, see module
+    pub fn module_uuid(&self) -> Option<&str> {
+        match self {
+            Self::BaseNotFirst(_) | Self::DuplicateUuid(_) => None,
+            Self::MissingDependency { module_uuid, .. }
+            | Self::DependencyAfterDependent { module_uuid, .. }
+            | Self::InvalidUuid {
+                uuid: module_uuid, ..
+            }
+            | Self::InvalidVersion { module_uuid, .. }
+            | Self::InvalidPublishHandle { module_uuid, .. } => Some(module_uuid),
+        }
+    }
+}
+
+// Checks an order against the game's load order contract: base first, no duplicates, dependencies
+// installed and loaded before their dependents, and well formed attribute values
+pub fn check_load_order(
+    order: &[ModuleDescription],
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+) -> Vec<ContractViolation> {
+    let mut violations = Vec::new();
+
+    if let Some(first) = order.first()
+        && first != base_mod
+    {
+        violations.push(ContractViolation::BaseNotFirst(first.name.clone()));
+    }
+
+    let mut positions = HashMap::new();
+    for (index, module) in order.iter().enumerate() {
+        if positions.insert(module.uuid.as_str(), index).is_some() {
+            violations.push(ContractViolation::DuplicateUuid(module.uuid.clone()));
+        }
+
+        if !is_valid_guid(&module.uuid) {
+            violations.push(ContractViolation::InvalidUuid {
+                module: module.name.clone(),
+                uuid: module.uuid.clone(),
+            });
+        }
+
+        if module.version64.parse::<i64>().is_err() {
+            violations.push(ContractViolation::InvalidVersion {
+                module: module.name.clone(),
+                module_uuid: module.uuid.clone(),
+                version: module.version64.clone(),
+            });
+        }
+
+        if let Some(publish_handle) = &module.publish_handle
+            && publish_handle.parse::<u64>().is_err()
+        {
+            violations.push(ContractViolation::InvalidPublishHandle {
+                module: module.name.clone(),
+                module_uuid: module.uuid.clone(),
+                publish_handle: publish_handle.clone(),
+            });
+        }
+    }
+
+    for module in mods {
+        let Some(module_position) = positions.get(module.description.uuid.as_str()) else {
+            continue;
+        };
+
+        for dependency in module
+            .dependencies
+            .iter()
+            .filter(|dependency| !dependency.is_base_game())
+        {
+            let installed = mods.iter().any(|other| other.description == *dependency);
+
+            if !installed {
+                violations.push(ContractViolation::MissingDependency {
+                    module: module.description.name.clone(),
+                    module_uuid: module.description.uuid.clone(),
+                    dependency: dependency.name.clone(),
+                });
+            } else if positions
+                .get(dependency.uuid.as_str())
+                .is_none_or(|position| position > module_position)
+            {
+                violations.push(ContractViolation::DependencyAfterDependent {
+                    module: module.description.name.clone(),
+                    module_uuid: module.description.uuid.clone(),
+                    dependency: dependency.name.clone(),
+                });
+            }
+        }
+    }
+
+    violations
+}
+
+// True for GUIDs in the canonical 8-4-4-4-12 hex digit format
+pub fn is_valid_guid(guid: &str) -> bool {
+    let groups: Vec<&str> = guid.split('-').collect();
+
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// Replace the modules in the "Mods" node
+pub fn set_modlist(modlist: &mut kiss_xml::dom::Element, order: &[ModuleDescription]) {
+    modlist.remove_elements_by_name("node");
+
+    for module in order {
+        modlist.append(module.as_xml());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // On-disk bytes of a file list entry
+    fn entry_bytes(name: &str, offset: u64, size: u32) -> Vec<u8> {
+        let mut bytes = vec![0; PAK_FILE_ENTRY_SIZE];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes[256..260].copy_from_slice(&(offset as u32).to_le_bytes());
+        bytes[260..262].copy_from_slice(&((offset >> 32) as u16).to_le_bytes());
+        bytes[263] = 0x02;
+        bytes[264..268].copy_from_slice(&size.to_le_bytes());
+        bytes[268..272].copy_from_slice(&(size * 2).to_le_bytes());
+        bytes
+    }
+
+    fn file_list(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| entry_bytes(&format!("Mods/Test/file{i}.lsx"), i as u64 * 100, 50))
+            .collect()
+    }
+
+    #[test]
+    fn entry_reader_streams_every_entry() {
+        let compressed = lz4_flex::compress(&file_list(300));
+        let entries: Vec<PAKFileEntry> = EntryReader::new(&compressed, 300)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(entries.len(), 300);
+        assert_eq!(entries[299].name(), "Mods/Test/file299.lsx");
+        assert_eq!(entries[299].offset(), 29900);
+    }
+
+    #[test]
+    fn offsets_cross_the_32_bit_boundary() {
+        for offset in [
+            0xffff_ffff,
+            0x1_0000_0000,
+            0x1_0000_0001,
+            0x1_2345_6789,
+            0xffff_ffff_ffff,
+        ] {
+            let entry = PAKFileEntry::parse(&entry_bytes("Mods/Test/meta.lsx", offset, 50));
+            assert_eq!(entry.offset(), offset, "{offset:#x}");
+        }
+    }
+
+    #[test]
+    fn entry_reader_rejects_extra_entries() {
+        let compressed = lz4_flex::compress(&file_list(3));
+        let results: Vec<_> = EntryReader::new(&compressed, 2).collect();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(Error::Pak(PAKError::Corrupt {
+                expected: 544,
+                actual: 816
+            }))
+        ));
+
+        let compressed = lz4_flex::compress(&file_list(1));
+        let results: Vec<_> = EntryReader::new(&compressed, 0).collect();
+        assert!(matches!(
+            results[..],
+            [Err(Error::Pak(PAKError::Corrupt {
+                expected: 0,
+                actual: 272
+            }))]
+        ));
+    }
+
+    #[test]
+    fn entry_reader_rejects_a_list_a_few_bytes_off() {
+        let list = file_list(3);
+
+        for (list, actual) in [
+            (list[..list.len() - 5].to_vec(), 811),
+            ([&list[..], &[0; 5]].concat(), 821),
+        ] {
+            let compressed = lz4_flex::compress(&list);
+            let results: Vec<_> = EntryReader::new(&compressed, 3).collect();
+
+            assert_eq!(results.len(), 3);
+            assert!(results[..2].iter().all(Result::is_ok));
+            assert!(matches!(
+                results[2],
+                Err(Error::Pak(PAKError::Corrupt { expected: 816, actual: a })) if a == actual
+            ));
+        }
+    }
+
+    fn installed(uuid: &str, version: Version, pak: &str) -> Module {
+        Module {
+            description: ModuleDescription {
+                name: String::from("Mod"),
+                uuid: uuid.to_string(),
+                written_uuid: uuid.to_string(),
+                version64: version.to_version64().to_string(),
+                ..Default::default()
+            },
+            pak: Some(PathBuf::from(pak)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dedup_keeps_the_copy_each_policy_prefers() {
+        let uuid = "00000000-0000-0000-0000-000000000001";
+        let mods = || {
+            vec![
+                installed(uuid, Version::new(1, 0, 0, 0), "a.pak"),
+                installed(uuid, Version::new(3, 0, 0, 0), "b.pak"),
+                installed(uuid, Version::new(2, 0, 0, 0), "c.pak"),
+            ]
+        };
+        let listed = |version: Version| {
+            vec![ModuleDescription {
+                uuid: uuid.to_string(),
+                version64: version.to_version64().to_string(),
+                ..Default::default()
+            }]
+        };
+
+        let (kept, duplicates) = dedup_mods(
+            mods(),
+            VersionPolicy::Existing,
+            &listed(Version::new(2, 0, 0, 0)),
+        );
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].pak.as_deref(), Some(Path::new("c.pak")));
+        assert_eq!(duplicates.len(), 2);
+
+        // No installed copy has the listed version, so the highest wins
+        let (kept, _) = dedup_mods(
+            mods(),
+            VersionPolicy::Existing,
+            &listed(Version::new(9, 0, 0, 0)),
+        );
+        assert_eq!(kept[0].pak.as_deref(), Some(Path::new("b.pak")));
+
+        let (kept, _) = dedup_mods(mods(), VersionPolicy::Lowest, &[]);
+        assert_eq!(kept[0].pak.as_deref(), Some(Path::new("a.pak")));
+
+        let (kept, duplicates) = dedup_mods(mods(), VersionPolicy::Highest, &[]);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].pak.as_deref(), Some(Path::new("b.pak")));
+        assert_eq!(duplicates.len(), 2);
+    }
+
+    #[test]
+    fn pretty_prints_minified_lsx() {
+        let minified = concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<save><version major="4" minor="0" revision="9" build="328"/><region id="Config">"#,
+            r#"<node id="root"><children><node id="ModuleInfo">"#,
+            r#"<attribute value="Test" type="LSString" id="Name"/>"#,
+            r#"</node></children></node></region></save>"#
+        );
+
+        assert_eq!(
+            lsx_string(&parse_lsx(minified.as_bytes()).unwrap()),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="0" revision="9" build="328"/>
+  <region id="Config">
+    <node id="root">
+      <children>
+        <node id="ModuleInfo">
+          <attribute id="Name" type="LSString" value="Test"/>
+        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#
+        );
+    }
+
+    fn uuid(n: u32) -> String {
+        format!("00000000-0000-0000-0000-{n:012}")
+    }
+
+    fn description(name: &str, uuid: &str) -> ModuleDescription {
+        ModuleDescription {
+            folder: name.to_string(),
+            name: name.to_string(),
+            uuid: uuid.to_lowercase(),
+            written_uuid: uuid.to_string(),
+            version64: Version::new(1, 0, 0, 0).to_version64().to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn depending(description: ModuleDescription, dependencies: &[&ModuleDescription]) -> Module {
+        Module {
+            description,
+            dependencies: dependencies
+                .iter()
+                .map(|&dependency| dependency.clone())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(order: &[ModuleDescription]) -> Vec<&str> {
+        order.iter().map(|module| module.name.as_str()).collect()
+    }
+
+    #[test]
+    fn shared_is_base_content() {
+        let shared = description("Shared", "ed539163-bb70-431b-96a7-f5b2eda5376b");
+        assert!(shared.is_base_game());
+
+        let mods = [depending(description("Mod", &uuid(1)), &[&shared])];
+        let (order, skipped) = resolve_order(
+            &mods,
+            &ModuleDescription::base_game(),
+            ResolveOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(names(&order), ["GustavX", "Mod"]);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].dependency, shared);
+        assert_eq!(skipped[0].reason, SkipReason::BaseGame);
+    }
+
+    #[test]
+    fn check_load_order_catches_each_rule() {
+        let base = ModuleDescription::base_game();
+        let dependency = description("Dependency", &uuid(1));
+        let dependent = description("Dependent", &uuid(2));
+        let mods = [
+            depending(dependency.clone(), &[]),
+            depending(dependent.clone(), &[&dependency]),
+        ];
+        let rules = |order: &[ModuleDescription], mods: &[Module]| -> Vec<&'static str> {
+            check_load_order(order, mods, &base)
+                .iter()
+                .map(ContractViolation::rule_id)
+                .collect()
+        };
+
+        let valid = [base.clone(), dependency.clone(), dependent.clone()];
+        assert!(rules(&valid, &mods).is_empty());
+
+        let order = [dependency.clone(), base.clone(), dependent.clone()];
+        assert_eq!(rules(&order, &mods), ["base-not-first"]);
+
+        let order = [
+            base.clone(),
+            dependency.clone(),
+            dependency.clone(),
+            dependent.clone(),
+        ];
+        assert_eq!(rules(&order, &mods), ["duplicate-uuid"]);
+
+        let order = [base.clone(), dependent.clone()];
+        assert_eq!(rules(&order, &mods[1..]), ["missing-dependency"]);
+
+        let order = [base.clone(), dependent.clone(), dependency.clone()];
+        assert_eq!(rules(&order, &mods), ["dependency-after-dependent"]);
+
+        let order = [base.clone(), description("Mod", "not-a-uuid")];
+        assert_eq!(rules(&order, &[]), ["invalid-uuid"]);
+
+        let order = [
+            base.clone(),
+            ModuleDescription {
+                version64: String::from("1.0"),
+                ..description("Mod", &uuid(3))
+            },
+        ];
+        assert_eq!(rules(&order, &[]), ["invalid-version"]);
+
+        let order = [
+            base.clone(),
+            ModuleDescription {
+                publish_handle: Some(String::from("-1")),
+                ..description("Mod", &uuid(3))
+            },
+        ];
+        assert_eq!(rules(&order, &[]), ["invalid-publish-handle"]);
+    }
+
+    #[test]
+    fn order_file_reorders_unless_it_breaks_a_dependency() {
+        let base = ModuleDescription::base_game();
+        let dependency = description("Dependency", &uuid(1));
+        let dependent = description("Dependent", &uuid(2));
+        let other = description("Other", &uuid(3));
+        let mods = [
+            depending(dependency.clone(), &[]),
+            depending(dependent.clone(), &[&dependency]),
+            depending(other.clone(), &[]),
+        ];
+        let order = vec![base.clone(), dependency, dependent, other];
+
+        let order_file =
+            std::env::temp_dir().join(format!("lsxwriter-order-{}", std::process::id()));
+        let apply = |lines: &str| {
+            fs::write(&order_file, lines).unwrap();
+            apply_order_file(order.clone(), &order_file, &mods, &base)
+        };
+
+        let (reordered, notices) = apply(&format!(
+            "# Other first\n{}\n\n{}\n{}\n",
+            uuid(3).to_uppercase(),
+            uuid(1),
+            uuid(9)
+        ))
+        .unwrap();
+        assert_eq!(
+            names(&reordered),
+            ["GustavX", "Other", "Dependency", "Dependent"]
+        );
+        assert!(matches!(notices[..], [Notice::NotInstalled(_)]));
+
+        let result = apply(&format!("{}\n{}\n", uuid(2), uuid(1)));
+        assert!(matches!(result, Err(Error::OrderFile(_))));
+
+        fs::remove_file(&order_file).unwrap();
+    }
+
+    #[test]
+    fn newest_base_module_is_chosen() {
+        let dev = description("GustavDev", "28ac9ce2-2aba-8cda-b3b5-6e922f71b6b8");
+        let newer = ModuleDescription {
+            version64: Version::new(4, 0, 0, 0).to_version64().to_string(),
+            ..ModuleDescription::base_game()
+        };
+        let mod_ = description("Mod", &uuid(1));
+
+        let base = find_base_module(&[dev.clone(), mod_.clone(), newer.clone()]).unwrap();
+        assert_eq!(base.name, "GustavX");
+
+        // Same version, so the earlier one
+        let base = find_base_module(&[dev, mod_.clone(), ModuleDescription::base_game()]).unwrap();
+        assert_eq!(base.name, "GustavDev");
+
+        assert!(find_base_module(&[mod_]).is_err());
+    }
+
+    #[test]
+    fn sample_opens_only_the_first_paks_by_name() {
+        let missing = std::env::temp_dir().join(format!("lsxwriter-sample-{}", std::process::id()));
+        let paths = ["c.pak", "a.pak", "b.pak"]
+            .map(|name| missing.join(name))
+            .to_vec();
+
+        let (paks, notices) = open_paks(
+            paths.clone(),
+            ScanOptions {
+                sample: Some(2),
+                ..Default::default()
+            },
+        );
+        assert!(paks.is_empty());
+        assert!(matches!(
+            notices[0],
+            Notice::Sampled {
+                scanned: 2,
+                total: 3
+            }
+        ));
+        assert_eq!(unreadable_paks(&notices), 2);
+
+        let (_, notices) = open_paks(paths, ScanOptions::default());
+        assert_eq!(notices.len(), 3);
+        assert_eq!(unreadable_paks(&notices), 3);
+    }
+
+    #[test]
+    fn ties_are_broken_by_uuid() {
+        let base = ModuleDescription::base_game();
+        let same = |n: u32| depending(description("Same", &uuid(n)), &[]);
+
+        for mods in [[same(1), same(2)], [same(2), same(1)]] {
+            let (order, _) = resolve_order(&mods, &base, ResolveOptions::default()).unwrap();
+            let uuids: Vec<&str> = order.iter().map(|module| module.uuid.as_str()).collect();
+            assert_eq!(uuids, [base.uuid.clone(), uuid(1), uuid(2)]);
+        }
+    }
+
+    #[test]
+    fn each_skip_reason_is_reported() {
+        let base = ModuleDescription::base_game();
+        let module = description("Mod", &uuid(1));
+        let missing = description("Missing", &uuid(2));
+        let mods = [depending(module.clone(), &[&base, &module, &missing])];
+
+        let (_, skipped) = resolve_order(&mods, &base, ResolveOptions::default()).unwrap();
+        let reasons: Vec<(&str, SkipReason)> = skipped
+            .iter()
+            .map(|skip| (skip.dependency.name.as_str(), skip.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("GustavX", SkipReason::BaseGame),
+                ("Mod", SkipReason::SelfDependency),
+                ("Missing", SkipReason::Missing)
+            ]
+        );
+        assert!(skipped.iter().all(|skip| skip.module == module));
+    }
+
+    // modsettings.lsx as the game writes it
+    const MODSETTINGS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="8" revision="0" build="10"/>
+  <region id="ModuleSettings">
+    <node id="root">
+      <children>
+        <node id="Mods">
+          <children>
+            <node id="ModuleShortDesc">
+              <attribute id="Folder" type="LSString" value="GustavX"/>
+              <attribute id="MD5" type="LSString" value=""/>
+              <attribute id="Name" type="LSString" value="GustavX"/>
+              <attribute id="PublishHandle" type="uint64" value="0"/>
+              <attribute id="UUID" type="guid" value="cb555efe-2d9e-131f-8195-a89329d218ea"/>
+              <attribute id="Version64" type="int64" value="36028797018963968"/>
+            </node>
+            <node id="ModuleShortDesc">
+              <attribute id="Folder" type="LSString" value="TavsHair_1a2b3c4d"/>
+              <attribute id="MD5" type="LSString" value="d41d8cd98f00b204e9800998ecf8427e"/>
+              <attribute id="Name" type="LSString" value="Tav's Hair &amp; More"/>
+              <attribute id="PublishHandle" type="uint64" value="4512345"/>
+              <attribute id="UUID" type="guid" value="1a2b3c4d-0000-4000-8000-000000000001"/>
+              <attribute id="Version64" type="int64" value="36169534507319297"/>
+            </node>
+          </children>
+        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#;
+
+    #[test]
+    fn rebuilding_keeps_region_and_version_attributes() {
+        let input = MODSETTINGS
+            .replace(
+                r#"<version major="4" minor="8" revision="0" build="10"/>"#,
+                r#"<version major="4" minor="7" revision="1" build="3" lslib_meta="v1,bswap_guids"/>"#,
+            )
+            .replace(
+                r#"<region id="ModuleSettings">"#,
+                r#"<region id="ModuleSettings" custom="kept">"#,
+            )
+            .replace(r#"<node id="root">"#, r#"<node id="root" key="root">"#);
+        let mut modsettings = ModSettings::parse(input.as_bytes()).unwrap();
+
+        let mods = modsettings.mods().to_vec();
+        modsettings.set_mods(vec![mods[0].clone()]);
+        modsettings.set_mods(mods);
+
+        assert_eq!(modsettings.to_lsx_string(), input);
+    }
+
+    #[test]
+    fn warns_when_a_mod_needs_a_newer_base_game() {
+        let base = ModuleDescription::base_game();
+        let newer_base = ModuleDescription {
+            version64: Version::new(4, 1, 0, 0).to_version64().to_string(),
+            ..ModuleDescription::base_game()
+        };
+        let mods = [
+            depending(description("Current", &uuid(1)), &[&base]),
+            depending(description("Future", &uuid(2)), &[&newer_base]),
+        ];
+
+        let warnings = base_version_warnings(&mods, &base);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].module_uuid(), Some(uuid(2).as_str()));
+        assert!(matches!(
+            warnings[0],
+            Warning::NewerBaseRequired {
+                required,
+                installed,
+                ..
+            } if required == Version::new(4, 1, 0, 0) && installed == Version::new(1, 0, 0, 0)
+        ));
+
+        assert!(base_version_warnings(&mods, &newer_base).is_empty());
+    }
+
+    #[test]
+    fn mixed_case_uuids_are_the_same_mod() {
+        let parse = |uuid: &str| {
+            let input = MODSETTINGS.replace("1a2b3c4d-0000-4000-8000-000000000001", uuid);
+            ModSettings::parse(input.as_bytes()).unwrap().mods()[1].clone()
+        };
+        let upper = parse("1A2B3C4D-0000-4000-8000-000000000001");
+        let lower = parse("1a2b3c4d-0000-4000-8000-000000000001");
+        assert_eq!(upper, lower);
+        assert_eq!(upper.uuid, lower.uuid);
+        assert_eq!(upper.written_uuid, "1A2B3C4D-0000-4000-8000-000000000001");
+        assert_eq!(HashSet::from([upper.clone(), lower.clone()]).len(), 1);
+
+        // A dependency written in upper case is satisfied by the mod installed in lower case
+        let base = ModuleDescription::base_game();
+        let mods = [
+            depending(description("Dependent", &uuid(1)), &[&upper]),
+            depending(lower, &[]),
+        ];
+        let (order, warnings) = resolve_order(&mods, &base, ResolveOptions::default()).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(names(&order), ["GustavX", "Tav's Hair & More", "Dependent"]);
+    }
+
+    #[test]
+    fn unpublished_handle_round_trips() {
+        let mut modsettings = ModSettings::parse(MODSETTINGS.as_bytes()).unwrap();
+        let mods = modsettings.mods().to_vec();
+        assert_eq!(mods[0].publish_handle, None);
+        assert_eq!(mods[1].publish_handle.as_deref(), Some("4512345"));
+
+        // Written the same as the GustavX entry constructed from scratch
+        modsettings.set_mods(vec![ModuleDescription::base_game(), mods[1].clone()]);
+        assert_eq!(modsettings.to_lsx_string(), MODSETTINGS);
+    }
+
+    #[test]
+    fn file_list_entry_layout() {
+        let mut bytes = [0; PAK_FILE_ENTRY_SIZE];
+        bytes[..21].copy_from_slice(b"Mods/Layout/meta.lsx\0");
+        bytes[21..256].fill(0xee);
+        bytes[256..272].copy_from_slice(&[
+            0x78, 0x56, 0x34, 0x12, // offset, low 32 bits
+            0x02, 0x01, // offset, high 16 bits
+            0x03, // archive part
+            0x42, // flags
+            0xef, 0xcd, 0xab, 0x00, // size on disk
+            0x10, 0x32, 0x54, 0x00, // uncompressed size
+        ]);
+
+        let entry = PAKFileEntry::parse(&bytes);
+        assert_eq!(entry.name(), "Mods/Layout/meta.lsx");
+        assert_eq!(entry.offset_in_file_1, 0x1234_5678);
+        assert_eq!(entry.offset_in_file_2, 0x0102);
+        assert_eq!(entry.offset(), 0x0102_1234_5678);
+        assert_eq!(entry.archive_part, 3);
+        assert_eq!(entry.flags, 0x42);
+        assert_eq!(entry.size_on_disk, 0xab_cdef);
+        assert_eq!(entry.uncompressed_size, 0x54_3210);
+    }
+
+    #[test]
+    fn modsettings_round_trips() {
+        let modsettings = ModSettings::parse(MODSETTINGS.as_bytes()).unwrap();
+        assert!(modsettings.notices().is_empty());
+        assert_eq!(names(modsettings.mods()), ["GustavX", "Tav's Hair & More"]);
+
+        let mut written = Vec::new();
+        modsettings.write(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), MODSETTINGS);
+    }
+
+    #[test]
+    fn modsettings_edits_keep_everything_else() {
+        let tav = "1a2b3c4d-0000-4000-8000-000000000001";
+        let other_region = r#"  <region id="Other">
+    <node id="root" kept="yes"/>
+  </region>
+</save>
+"#;
+        let input = MODSETTINGS.replace("</save>\n", other_region);
+        let mut modsettings = ModSettings::parse(input.as_bytes()).unwrap();
+
+        modsettings.add(description("New", &uuid(5)));
+        assert!(modsettings.move_to(&uuid(5), 1));
+        assert!(!modsettings.move_to(&uuid(6), 0));
+        modsettings.add(ModuleDescription {
+            name: String::from("Tav's Hair"),
+            ..modsettings.mods()[2].clone()
+        });
+        assert_eq!(names(modsettings.mods()), ["GustavX", "New", "Tav's Hair"]);
+
+        let removed = modsettings.remove(&tav.to_uppercase()).unwrap();
+        assert_eq!(removed.name, "Tav's Hair");
+        assert!(modsettings.remove(tav).is_none());
+
+        let written = modsettings.to_lsx_string();
+        assert!(written.ends_with(other_region));
+        let reparsed = ModSettings::parse(written.as_bytes()).unwrap();
+        assert_eq!(names(reparsed.mods()), ["GustavX", "New"]);
+        assert_eq!(reparsed.mods()[1].uuid, uuid(5));
+
+        modsettings.set_mods(
+            ModSettings::parse(MODSETTINGS.as_bytes())
+                .unwrap()
+                .mods()
+                .to_vec(),
+        );
+        assert_eq!(modsettings.to_lsx_string(), input);
+    }
+
+    #[test]
+    fn byte_order_mark_round_trips() {
+        let input = format!("{BOM}{MODSETTINGS}");
+        let mut modsettings = ModSettings::parse(input.as_bytes()).unwrap();
+        assert_eq!(modsettings.mods().len(), 2);
+        assert_eq!(modsettings.to_lsx_string(), input);
+
+        let mods = modsettings.mods().to_vec();
+        modsettings.set_mods(mods);
+        assert_eq!(modsettings.to_lsx_string(), input);
+
+        let without = ModSettings::parse(MODSETTINGS.as_bytes()).unwrap();
+        assert_eq!(without.to_lsx_string(), MODSETTINGS);
+    }
+
+    #[test]
+    fn lsx_is_written_like_the_game_writes_it() {
+        let doc = parse_lsx(MODSETTINGS.as_bytes()).unwrap();
+        assert_eq!(lsx_string(&doc), MODSETTINGS);
+
+        // Entries built from scratch, through as_xml
+        let mut modsettings = ModSettings::parse(MODSETTINGS.as_bytes()).unwrap();
+        let rebuilt = modsettings
+            .mods()
+            .iter()
+            .map(|module| ModuleDescription {
+                attribute_types: HashMap::new(),
+                ..module.clone()
+            })
+            .collect();
+        modsettings.set_mods(rebuilt);
+        assert_eq!(modsettings.to_lsx_string(), MODSETTINGS);
+    }
+
+    #[test]
+    fn guids_must_be_canonical() {
+        for valid in [
+            "cb555efe-2d9e-131f-8195-a89329d218ea",
+            "CB555EFE-2D9E-131F-8195-A89329D218EA",
+            "00000000-0000-0000-0000-000000000000",
+        ] {
+            assert!(is_valid_guid(valid), "{valid}");
+        }
+        for invalid in [
+            "",
+            "cb555efe2d9e131f8195a89329d218ea",
+            "{cb555efe-2d9e-131f-8195-a89329d218ea}",
+            "cb555efe-2d9e-131f-8195-a89329d218e",
+            "cb555efe-2d9e-131f-8195-a89329d218eaa",
+            "cb555efe-2d9e-131f-8195a-89329d218ea",
+            "cb555efg-2d9e-131f-8195-a89329d218ea",
+            "cb555efe-2d9e-131f-8195-a89329d218ea-",
+            " cb555efe-2d9e-131f-8195-a89329d218ea",
+        ] {
+            assert!(!is_valid_guid(invalid), "{invalid}");
+        }
+
+        let mods = [
+            depending(description("Valid", &uuid(1)), &[]),
+            depending(description("Broken", "Not-A-Guid"), &[]),
+        ];
+        let warnings = invalid_uuid_warnings(&mods);
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            &warnings[0],
+            Warning::InvalidUuid { module, uuid } if module == "Broken" && uuid == "Not-A-Guid"
+        ));
+    }
+
+    #[test]
+    fn each_missing_node_is_reported_and_repaired() {
+        let chain = [
+            ("region", "ModuleSettings", "save"),
+            ("node", "root", "region"),
+            ("children", "root", "node"),
+            ("node", "Mods", "children"),
+            ("children", "Mods", "node"),
+        ];
+        // The document down to (but without) the link at each depth
+        let truncated = [
+            "<save/>",
+            r#"<save><region id="ModuleSettings"/></save>"#,
+            r#"<save><region id="ModuleSettings"><node id="root"/></region></save>"#,
+            r#"<save><region id="ModuleSettings"><node id="root"><children/></node></region></save>"#,
+            r#"<save><region id="ModuleSettings"><node id="root"><children><node id="Mods"/></children></node></region></save>"#,
+        ];
+
+        for (depth, input) in truncated.iter().enumerate() {
+            let (name, id, parent) = chain[depth];
+            let Err(Error::ModSettings(ModSettingsError::MissingNode(node))) =
+                ModSettings::parse(input.as_bytes())
+            else {
+                panic!("parsed without {name} ({id})");
+            };
+            assert_eq!(node, format!("{name} ({id}) inside {parent}"));
+
+            let repaired = ModSettings::parse_with(input.as_bytes(), true).unwrap();
+            let rebuilt: Vec<Notice> = chain[depth..]
+                .iter()
+                .map(|(name, id, _)| Notice::RepairedNode {
+                    name: name.to_string(),
+                    id: id.to_string(),
+                })
+                .collect();
+            assert_eq!(repaired.notices()[..rebuilt.len()], rebuilt);
+            assert!(matches!(
+                repaired.notices()[rebuilt.len()..],
+                [Notice::AddedBaseModule { .. }]
+            ));
+
+            // The skeleton is complete, so the written file parses without repair
+            let reparsed = ModSettings::parse(repaired.to_lsx_string().as_bytes()).unwrap();
+            assert_eq!(names(reparsed.mods()), ["GustavX"]);
+        }
+    }
+
+    // meta.lsx with the given ModuleShortDesc nodes as dependencies and attributes in ModuleInfo
+    fn meta_lsx(dependencies: &str, module_info: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="0" revision="9" build="328"/>
+  <region id="Config">
+    <node id="root">
+      <children>
+        <node id="Dependencies">
+          <children>
+{dependencies}          </children>
+        </node>
+        <node id="ModuleInfo">
+{module_info}        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#
+        )
+    }
+
+    const MODULE_INFO: &str = r#"          <attribute id="Folder" type="LSString" value="Meta"/>
+          <attribute id="MD5" type="LSString" value=""/>
+          <attribute id="Name" type="LSString" value="Meta"/>
+          <attribute id="UUID" type="FixedString" value="00000000-0000-0000-0000-000000000001"/>
+          <attribute id="Version64" type="int64" value="36028797018963968"/>
+"#;
+
+    #[test]
+    fn cdata_descriptions_are_extracted() {
+        // Wrapped in the value attribute, and as the element's content
+        for (description, expected) in [
+            (
+                r#"          <attribute id="Description" type="LSString" value="&lt;![CDATA[Hair &amp; more]]&gt;"/>
+"#,
+                "Hair & more",
+            ),
+            (
+                r#"          <attribute id="Description" type="LSString"><![CDATA[Hair & <b>more</b>]]></attribute>
+"#,
+                "Hair & <b>more</b>",
+            ),
+        ] {
+            let meta = meta_lsx("", &format!("{description}{MODULE_INFO}"));
+            let module = Module::from_meta_bytes(meta.as_bytes()).unwrap();
+            assert_eq!(module.description.description.as_deref(), Some(expected));
+        }
+    }
+
+    const DEPENDENCY: &str = r#"            <node id="ModuleShortDesc">
+              <attribute id="Folder" type="LSString" value="Library"/>
+              <attribute id="MD5" type="LSString" value=""/>
+              <attribute id="Name" type="LSString" value="Library"/>
+              <attribute id="PublishHandle" type="uint64" value="0"/>
+              <attribute id="UUID" type="guid" value="00000000-0000-0000-0000-000000000002"/>
+              <attribute id="Version64" type="int64" value="36028797018963968"/>
+            </node>
+"#;
+
+    #[test]
+    fn module_info_sub_nodes_survive_conversion() {
+        let sub_nodes = r#"          <children>
+            <node id="PublishVersion">
+              <attribute id="Version64" type="int64" value="36028797018963968"/>
+            </node>
+            <node id="Files">
+              <children>
+                <node id="File">
+                  <attribute id="Path" type="LSString" value="Public/Meta/Stats/Generated/Data/Armor.txt"/>
+                </node>
+              </children>
+            </node>
+            <node id="CharacterCreationPreset">
+              <attribute id="Object" type="guid" value="00000000-0000-0000-0000-000000000003"/>
+            </node>
+          </children>
+"#;
+        let meta = meta_lsx(DEPENDENCY, &format!("{MODULE_INFO}{sub_nodes}"));
+        let module = Module::from_meta_bytes(meta.as_bytes()).unwrap();
+        assert_eq!(lsx_string(&module.as_meta_xml()), meta);
+
+        // Through the compiled format and back
+        let lsf = lsf::write(&module.as_meta_xml()).unwrap();
+        let module = Module::from_meta_bytes(&lsf).unwrap();
+        assert_eq!(lsx_string(&module.as_meta_xml()), meta);
+    }
+
+    #[test]
+    fn string_types_are_written_back_as_read() {
+        for ty in ["FixedString", "LSWString", "LSString"] {
+            let module_info = MODULE_INFO.replace(
+                r#"<attribute id="Name" type="LSString""#,
+                &format!(r#"<attribute id="Name" type="{ty}""#),
+            );
+            let meta = meta_lsx("", &module_info);
+            let description = Module::from_meta_bytes(meta.as_bytes())
+                .unwrap()
+                .description;
+            assert_eq!(description.name, "Meta");
+            assert_eq!(description.attribute_type("Name", "LSString"), ty);
+
+            let short_desc = description.as_xml();
+            let name = short_desc
+                .child_elements()
+                .find(|att| att.get_attr("id").is_some_and(|id| id == "Name"))
+                .unwrap();
+            assert_eq!(name.get_attr("type").map(String::as_str), Some(ty));
+            assert_eq!(name.get_attr("value").map(String::as_str), Some("Meta"));
+        }
+    }
+
+    #[test]
+    fn build_round_trips() {
+        let module_info = MODULE_INFO.replace(
+            "          <attribute id=\"Version64\"",
+            "          <attribute id=\"Build\" type=\"int32\" value=\"42\"/>\n          <attribute id=\"Version64\"",
+        );
+        let meta = meta_lsx("", &module_info);
+        let description = Module::from_meta_bytes(meta.as_bytes())
+            .unwrap()
+            .description;
+        assert_eq!(description.build.as_deref(), Some("42"));
+
+        let mut modsettings = ModSettings::parse(MODSETTINGS.as_bytes()).unwrap();
+        modsettings.add(description);
+        let written = modsettings.to_lsx_string();
+        assert!(written.contains(r#"<attribute id="Build" type="int32" value="42"/>"#));
+        let reparsed = ModSettings::parse(written.as_bytes()).unwrap();
+        assert_eq!(reparsed.mods()[2].build.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn wrapped_dependencies_are_found() {
+        let second = DEPENDENCY
+            .replace("Library", "Framework")
+            .replace("000000000002", "000000000003");
+        let wrapped = format!(
+            r#"            <node id="DependencyData">
+              <children>
+{DEPENDENCY}              </children>
+            </node>
+{second}"#
+        );
+        let meta = meta_lsx(&wrapped, MODULE_INFO);
+
+        let module = Module::from_meta_bytes(meta.as_bytes()).unwrap();
+        assert_eq!(names(&module.dependencies), ["Library", "Framework"]);
+        assert_eq!(module.dependencies[0].uuid, uuid(2));
+    }
+
+    #[test]
+    fn handle_only_attributes_are_captured() {
+        let display_name = r#"          <attribute id="DisplayName" type="TranslatedString" handle="h5a1c0e7fg0b2dg4f1eg9c3ag1d2e3f4a5b6c" version="2"/>
+"#;
+        let meta = meta_lsx(DEPENDENCY, &format!("{display_name}{MODULE_INFO}"));
+        let module = Module::from_meta_bytes(meta.as_bytes()).unwrap();
+
+        assert_eq!(
+            module.description.translated.get("DisplayName"),
+            Some(&TranslatedString {
+                handle: String::from("h5a1c0e7fg0b2dg4f1eg9c3ag1d2e3f4a5b6c"),
+                version: Some(String::from("2")),
+            })
+        );
+        assert_eq!(module.description.translated.len(), 1);
+        assert_eq!(lsx_string(&module.as_meta_xml()), meta);
+    }
+}
@@ -0,0 +1,757 @@
+use crate::version::Version;
+use kiss_xml::dom::{Declaration, Document, Element};
+use std::{collections::HashMap, fmt::Display, io::Read, str::FromStr};
+
+const MAGIC: &[u8; 4] = b"LSOF";
+// Written by older tools, same layout otherwise
+const LEGACY_MAGIC: &[u8; 4] = b"LSFM";
+
+// BG3 extended header: 64 bit engine version, no node keys section
+const WRITE_VERSION: u32 = 5;
+
+const NAME_BUCKETS: usize = 0x200;
+
+// Metadata format that uses the larger node/attribute entries with sibling links
+const FORMAT_KEYS_AND_ADJACENCY: u32 = 1;
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_LZ4: u8 = 2;
+
+// LSLib's attribute type ids and the names LSX uses for them
+const TYPES: &[(u32, &str)] = &[
+    (1, "uint8"),
+    (2, "int16"),
+    (3, "uint16"),
+    (4, "int32"),
+    (5, "uint32"),
+    (6, "float"),
+    (7, "double"),
+    (19, "bool"),
+    (20, "string"),
+    (21, "path"),
+    (22, "FixedString"),
+    (23, "LSString"),
+    (24, "uint64"),
+    (26, "old_int64"),
+    (27, "int8"),
+    (28, "TranslatedString"),
+    (29, "WString"),
+    (30, "LSWString"),
+    (31, "guid"),
+    (32, "int64"),
+];
+
+#[derive(Debug)]
+pub enum LSFError {
+    BadMagic(String),
+    UnsupportedVersion(u32),
+    UnsupportedCompression(u8),
+    UnsupportedType(String),
+    BadValue { id: String, value: String },
+    MissingNode(String),
+    Truncated,
+}
+
+impl std::error::Error for LSFError {}
+
+impl Display for LSFError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic(magic) => {
+                write!(f, "Bad header magic value: {magic} (should be \"LSOF\")")
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported LSF version {version}")
+            }
+            Self::UnsupportedCompression(method) => {
+                write!(f, "Unsupported LSF compression method {method}")
+            }
+            Self::UnsupportedType(ty) => {
+                write!(f, "Attributes of type {ty} can't be stored in LSF yet")
+            }
+            Self::BadValue { id, value } => {
+                write!(f, "Attribute {id} has an invalid value: {value}")
+            }
+            Self::MissingNode(node) => {
+                write!(f, "Document is missing the {node} node")
+            }
+            Self::Truncated => {
+                write!(f, "LSF data ends early (corrupt or truncated file)")
+            }
+        }
+    }
+}
+
+// Serializes an LSX document (save > version + regions) to LSF. Each region becomes a top level
+// node named after the region id, the way LSLib names them
+pub fn write(doc: &Document) -> Result<Vec<u8>, LSFError> {
+    let save = doc.root_element();
+
+    let version = save
+        .first_element_by_name("version")
+        .map_err(|_| LSFError::MissingNode(String::from("version")))?;
+    let version_part = |name: &str| {
+        version
+            .get_attr(name)
+            .and_then(|part| part.parse().ok())
+            .unwrap_or(0)
+    };
+    let engine_version = Version::new(
+        version_part("major") as u16,
+        version_part("minor") as u16,
+        version_part("revision") as u16,
+        version_part("build"),
+    );
+
+    let mut writer = Writer::default();
+    for region in save.elements_by_name("region") {
+        let id = region
+            .get_attr("id")
+            .ok_or_else(|| LSFError::MissingNode(String::from("region id")))?;
+        let node = region
+            .first_element_by_name("node")
+            .map_err(|_| LSFError::MissingNode(format!("{id} region's node")))?;
+        writer.write_node(node, id, -1)?;
+    }
+
+    let strings = writer.strings();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&WRITE_VERSION.to_le_bytes());
+    out.extend_from_slice(&engine_version.to_version64().to_le_bytes());
+
+    // Sections are stored uncompressed, which readers recognise by a zero size on disk
+    for section in [&strings, &writer.nodes, &writer.attributes, &writer.values] {
+        out.extend_from_slice(&(section.len() as u32).to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+    }
+    out.push(COMPRESSION_NONE);
+    out.push(0);
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+
+    for section in [&strings, &writer.nodes, &writer.attributes, &writer.values] {
+        out.extend_from_slice(section);
+    }
+
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Writer {
+    names: HashMap<String, u32>,
+    buckets: Vec<Vec<String>>,
+    nodes: Vec<u8>,
+    attributes: Vec<u8>,
+    values: Vec<u8>,
+    node_count: i32,
+    attribute_count: i32,
+}
+
+impl Writer {
+    // Names are referenced by (bucket << 16 | index in bucket). Any bucket works as long as the
+    // reference points at it, so a simple hash is enough
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(reference) = self.names.get(name) {
+            return *reference;
+        }
+
+        if self.buckets.is_empty() {
+            self.buckets = vec![Vec::new(); NAME_BUCKETS];
+        }
+
+        let bucket = name.bytes().fold(0usize, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as usize)
+        }) % NAME_BUCKETS;
+        let reference = ((bucket as u32) << 16) | self.buckets[bucket].len() as u32;
+        self.buckets[bucket].push(name.to_string());
+        self.names.insert(name.to_string(), reference);
+        reference
+    }
+
+    fn strings(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.buckets.len() as u32).to_le_bytes());
+        for bucket in &self.buckets {
+            out.extend_from_slice(&(bucket.len() as u16).to_le_bytes());
+            for name in bucket {
+                out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                out.extend_from_slice(name.as_bytes());
+            }
+        }
+        out
+    }
+
+    fn write_node(&mut self, node: &Element, name: &str, parent: i32) -> Result<(), LSFError> {
+        let index = self.node_count;
+        self.node_count += 1;
+
+        let attributes: Vec<&Element> = node.elements_by_name("attribute").collect();
+        let first_attribute = if attributes.is_empty() {
+            -1
+        } else {
+            self.attribute_count
+        };
+
+        let name = self.name(name);
+        self.nodes.extend_from_slice(&name.to_le_bytes());
+        self.nodes.extend_from_slice(&first_attribute.to_le_bytes());
+        self.nodes.extend_from_slice(&parent.to_le_bytes());
+
+        for attribute in attributes {
+            let id = attribute.get_attr("id").map_or("", String::as_str);
+            let ty = attribute.get_attr("type").map_or("", String::as_str);
+            let type_id = TYPES
+                .iter()
+                .find(|(_, name)| *name == ty)
+                .map(|(type_id, _)| *type_id)
+                .ok_or_else(|| LSFError::UnsupportedType(ty.to_string()))?;
+
+            let value = encode_value(attribute, id, ty)?;
+
+            let name = self.name(id);
+            self.attributes.extend_from_slice(&name.to_le_bytes());
+            self.attributes
+                .extend_from_slice(&(type_id | ((value.len() as u32) << 6)).to_le_bytes());
+            self.attributes.extend_from_slice(&index.to_le_bytes());
+            self.values.extend_from_slice(&value);
+            self.attribute_count += 1;
+        }
+
+        for children in node.elements_by_name("children") {
+            for child in children.elements_by_name("node") {
+                let child_name = child.get_attr("id").map_or("", String::as_str);
+                self.write_node(child, child_name, index)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_value(attribute: &Element, id: &str, ty: &str) -> Result<Vec<u8>, LSFError> {
+    if ty == "TranslatedString" {
+        let handle = attribute.get_attr("handle").map_or("", String::as_str);
+        let version: u16 = attribute
+            .get_attr("version")
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(0);
+
+        let mut out = version.to_le_bytes().to_vec();
+        out.extend_from_slice(&(handle.len() as i32 + 1).to_le_bytes());
+        out.extend_from_slice(handle.as_bytes());
+        out.push(0);
+        return Ok(out);
+    }
+
+    let value = attribute.get_attr("value").map_or("", String::as_str);
+    let bad_value = || LSFError::BadValue {
+        id: id.to_string(),
+        value: value.to_string(),
+    };
+
+    fn number<T: FromStr>(value: &str) -> Option<T> {
+        value.trim().parse().ok()
+    }
+
+    let bytes = match ty {
+        "uint8" => number::<u8>(value).map(|v| v.to_le_bytes().to_vec()),
+        "int8" => number::<i8>(value).map(|v| v.to_le_bytes().to_vec()),
+        "int16" => number::<i16>(value).map(|v| v.to_le_bytes().to_vec()),
+        "uint16" => number::<u16>(value).map(|v| v.to_le_bytes().to_vec()),
+        "int32" => number::<i32>(value).map(|v| v.to_le_bytes().to_vec()),
+        "uint32" => number::<u32>(value).map(|v| v.to_le_bytes().to_vec()),
+        "int64" | "old_int64" => number::<i64>(value).map(|v| v.to_le_bytes().to_vec()),
+        "uint64" => number::<u64>(value).map(|v| v.to_le_bytes().to_vec()),
+        "float" => number::<f32>(value).map(|v| v.to_le_bytes().to_vec()),
+        "double" => number::<f64>(value).map(|v| v.to_le_bytes().to_vec()),
+        "bool" => match value.to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(vec![1]),
+            "false" | "0" => Some(vec![0]),
+            _ => None,
+        },
+        "guid" => guid_to_bytes(value).map(|bytes| bytes.to_vec()),
+        // The string types are all stored null terminated
+        _ => {
+            let mut out = value.as_bytes().to_vec();
+            out.push(0);
+            Some(out)
+        }
+    };
+
+    bytes.ok_or_else(bad_value)
+}
+
+// BG3 stores guids as .NET does (first three groups little endian) with each pair of the last
+// eight bytes swapped
+fn guid_to_bytes(guid: &str) -> Option<[u8; 16]> {
+    let hex: String = guid.chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    for pair in bytes[8..].chunks_mut(2) {
+        pair.swap(0, 1);
+    }
+
+    Some(bytes)
+}
+
+fn guid_from_bytes(mut bytes: [u8; 16]) -> String {
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    for pair in bytes[8..].chunks_mut(2) {
+        pair.swap(0, 1);
+    }
+
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LSFError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(LSFError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LSFError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, LSFError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LSFError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, LSFError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, LSFError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, LSFError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    // Reads one section, decompressing it if needed. Strings are compressed as a single block,
+    // the other sections as an lz4 frame
+    fn section(
+        &mut self,
+        (uncompressed_size, size_on_disk): (u32, u32),
+        compression: u8,
+        framed: bool,
+    ) -> Result<Vec<u8>, LSFError> {
+        if size_on_disk == 0 {
+            return Ok(self.take(uncompressed_size as usize)?.to_vec());
+        }
+
+        let data = self.take(size_on_disk as usize)?;
+        match compression {
+            COMPRESSION_NONE => Ok(data.to_vec()),
+            COMPRESSION_LZ4 if framed => {
+                let mut out = Vec::with_capacity(uncompressed_size as usize);
+                lz4_flex::frame::FrameDecoder::new(data)
+                    .read_to_end(&mut out)
+                    .map_err(|_| LSFError::Truncated)?;
+                Ok(out)
+            }
+            COMPRESSION_LZ4 => lz4_flex::block::decompress(data, uncompressed_size as usize)
+                .map_err(|_| LSFError::Truncated),
+            method => Err(LSFError::UnsupportedCompression(method)),
+        }
+    }
+}
+
+struct NodeEntry {
+    name: u32,
+    parent: i32,
+    attributes: Vec<usize>,
+}
+
+struct AttributeEntry {
+    name: u32,
+    type_id: u32,
+    offset: usize,
+    len: usize,
+}
+
+// True if data starts like an LSF file rather than LSX text
+pub fn is_lsf(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.starts_with(LEGACY_MAGIC)
+}
+
+// Parses an LSF file back into the same document shape `write` takes
+pub fn read(data: &[u8]) -> Result<Document, LSFError> {
+    let mut cursor = Cursor { data, pos: 0 };
+
+    let magic = cursor.array::<4>()?;
+    if &magic != MAGIC && &magic != LEGACY_MAGIC {
+        return Err(LSFError::BadMagic(
+            String::from_utf8_lossy(&magic).into_owned(),
+        ));
+    }
+
+    let version = cursor.u32()?;
+    if !(5..=7).contains(&version) {
+        return Err(LSFError::UnsupportedVersion(version));
+    }
+
+    let engine_version = Version::from_version64(cursor.u64()?);
+
+    let mut sizes = || -> Result<(u32, u32), LSFError> { Ok((cursor.u32()?, cursor.u32()?)) };
+    let strings_size = sizes()?;
+    let keys_size = if version >= 6 { sizes()? } else { (0, 0) };
+    let nodes_size = sizes()?;
+    let attributes_size = sizes()?;
+    let values_size = sizes()?;
+    let compression = cursor.u8()? & 0x0f;
+    cursor.u8()?;
+    cursor.u16()?;
+    let format = cursor.u32()?;
+
+    let strings = cursor.section(strings_size, compression, false)?;
+    let nodes = cursor.section(nodes_size, compression, true)?;
+    let attributes = cursor.section(attributes_size, compression, true)?;
+    let values = cursor.section(values_size, compression, true)?;
+    // Node keys aren't part of the LSX representation
+    if keys_size != (0, 0) {
+        cursor.section(keys_size, compression, true)?;
+    }
+
+    let names = read_names(&strings)?;
+
+    let (nodes, attributes) = if format == FORMAT_KEYS_AND_ADJACENCY {
+        read_entries_v3(&nodes, &attributes)?
+    } else {
+        read_entries_v2(&nodes, &attributes)?
+    };
+
+    let mut children: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (index, node) in nodes.iter().enumerate() {
+        children.entry(node.parent).or_default().push(index);
+    }
+
+    let tree = Tree {
+        names,
+        nodes,
+        attributes,
+        children,
+        values,
+    };
+
+    let mut save = Element::new_from_name("save").unwrap();
+    save.append(
+        Element::new_with_attributes(
+            "version",
+            HashMap::from([
+                ("major", engine_version.major.to_string()),
+                ("minor", engine_version.minor.to_string()),
+                ("revision", engine_version.revision.to_string()),
+                ("build", engine_version.build.to_string()),
+            ]),
+        )
+        .unwrap(),
+    );
+
+    for region in tree.children.get(&-1).into_iter().flatten() {
+        let mut element = Element::new_from_name("region").unwrap();
+        element
+            .set_attr("id", tree.name(tree.nodes[*region].name)?)
+            .unwrap();
+        element.append(tree.build(*region, "root")?);
+        save.append(element);
+    }
+
+    Ok(Document::new_with_decl_dtd(
+        save,
+        Some(Declaration::from_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap()),
+        None,
+    ))
+}
+
+struct Tree {
+    names: Vec<Vec<String>>,
+    nodes: Vec<NodeEntry>,
+    attributes: Vec<AttributeEntry>,
+    children: HashMap<i32, Vec<usize>>,
+    values: Vec<u8>,
+}
+
+impl Tree {
+    fn name(&self, reference: u32) -> Result<&str, LSFError> {
+        self.names
+            .get((reference >> 16) as usize)
+            .and_then(|bucket| bucket.get((reference & 0xffff) as usize))
+            .map(String::as_str)
+            .ok_or(LSFError::Truncated)
+    }
+
+    fn build(&self, index: usize, id: &str) -> Result<Element, LSFError> {
+        let mut element = Element::new_from_name("node").unwrap();
+        element.set_attr("id", id).unwrap();
+
+        for attribute in self.nodes[index].attributes.iter() {
+            let attribute = &self.attributes[*attribute];
+            let value = self
+                .values
+                .get(attribute.offset..attribute.offset + attribute.len)
+                .ok_or(LSFError::Truncated)?;
+            element.append(decode_attribute(
+                self.name(attribute.name)?,
+                attribute.type_id,
+                value,
+            )?);
+        }
+
+        if let Some(children) = self.children.get(&(index as i32)) {
+            let mut wrapper = Element::new_from_name("children").unwrap();
+            for child in children {
+                wrapper.append(self.build(*child, self.name(self.nodes[*child].name)?)?);
+            }
+            element.append(wrapper);
+        }
+
+        Ok(element)
+    }
+}
+
+fn read_names(strings: &[u8]) -> Result<Vec<Vec<String>>, LSFError> {
+    let mut cursor = Cursor {
+        data: strings,
+        pos: 0,
+    };
+
+    let mut buckets = Vec::new();
+    for _ in 0..cursor.u32()? {
+        let mut bucket = Vec::new();
+        for _ in 0..cursor.u16()? {
+            let len = cursor.u16()? as usize;
+            bucket.push(String::from_utf8_lossy(cursor.take(len)?).into_owned());
+        }
+        buckets.push(bucket);
+    }
+
+    Ok(buckets)
+}
+
+// Entries without sibling links: attributes are listed in node order and their values follow
+// each other in the values section
+fn read_entries_v2(
+    nodes: &[u8],
+    attributes: &[u8],
+) -> Result<(Vec<NodeEntry>, Vec<AttributeEntry>), LSFError> {
+    let mut node_entries = Vec::new();
+    let mut cursor = Cursor {
+        data: nodes,
+        pos: 0,
+    };
+    while !cursor.is_empty() {
+        let name = cursor.u32()?;
+        let _first_attribute = cursor.i32()?;
+        let parent = cursor.i32()?;
+        node_entries.push(NodeEntry {
+            name,
+            parent,
+            attributes: Vec::new(),
+        });
+    }
+
+    let mut attribute_entries = Vec::new();
+    let mut offset = 0;
+    let mut cursor = Cursor {
+        data: attributes,
+        pos: 0,
+    };
+    while !cursor.is_empty() {
+        let name = cursor.u32()?;
+        let type_and_length = cursor.u32()?;
+        let node = cursor.i32()?;
+        let len = (type_and_length >> 6) as usize;
+
+        node_entries
+            .get_mut(node as usize)
+            .ok_or(LSFError::Truncated)?
+            .attributes
+            .push(attribute_entries.len());
+        attribute_entries.push(AttributeEntry {
+            name,
+            type_id: type_and_length & 0x3f,
+            offset,
+            len,
+        });
+        offset += len;
+    }
+
+    Ok((node_entries, attribute_entries))
+}
+
+// Entries with sibling links and explicit value offsets
+fn read_entries_v3(
+    nodes: &[u8],
+    attributes: &[u8],
+) -> Result<(Vec<NodeEntry>, Vec<AttributeEntry>), LSFError> {
+    let mut attribute_entries = Vec::new();
+    let mut next_attribute = Vec::new();
+    let mut cursor = Cursor {
+        data: attributes,
+        pos: 0,
+    };
+    while !cursor.is_empty() {
+        let name = cursor.u32()?;
+        let type_and_length = cursor.u32()?;
+        next_attribute.push(cursor.i32()?);
+        let offset = cursor.u32()? as usize;
+        attribute_entries.push(AttributeEntry {
+            name,
+            type_id: type_and_length & 0x3f,
+            offset,
+            len: (type_and_length >> 6) as usize,
+        });
+    }
+
+    let mut node_entries = Vec::new();
+    let mut cursor = Cursor {
+        data: nodes,
+        pos: 0,
+    };
+    while !cursor.is_empty() {
+        let name = cursor.u32()?;
+        let parent = cursor.i32()?;
+        let _next_sibling = cursor.i32()?;
+        let mut attribute = cursor.i32()?;
+
+        let mut attributes = Vec::new();
+        while attribute >= 0 && attributes.len() < attribute_entries.len() {
+            attributes.push(attribute as usize);
+            attribute = *next_attribute
+                .get(attribute as usize)
+                .ok_or(LSFError::Truncated)?;
+        }
+
+        node_entries.push(NodeEntry {
+            name,
+            parent,
+            attributes,
+        });
+    }
+
+    Ok((node_entries, attribute_entries))
+}
+
+fn decode_attribute(id: &str, type_id: u32, value: &[u8]) -> Result<Element, LSFError> {
+    let ty = TYPES
+        .iter()
+        .find(|(known, _)| *known == type_id)
+        .map(|(_, name)| *name)
+        .ok_or_else(|| LSFError::UnsupportedType(type_id.to_string()))?;
+
+    let mut attributes = HashMap::from([("id", id.to_string()), ("type", ty.to_string())]);
+
+    let mut cursor = Cursor {
+        data: value,
+        pos: 0,
+    };
+
+    let text = match ty {
+        "uint8" => cursor.u8()?.to_string(),
+        "int8" => (cursor.u8()? as i8).to_string(),
+        "int16" => i16::from_le_bytes(cursor.array()?).to_string(),
+        "uint16" => cursor.u16()?.to_string(),
+        "int32" => cursor.i32()?.to_string(),
+        "uint32" => cursor.u32()?.to_string(),
+        "int64" | "old_int64" => i64::from_le_bytes(cursor.array()?).to_string(),
+        "uint64" => cursor.u64()?.to_string(),
+        "float" => f32::from_le_bytes(cursor.array()?).to_string(),
+        "double" => f64::from_le_bytes(cursor.array()?).to_string(),
+        "bool" => String::from(if cursor.u8()? != 0 { "True" } else { "False" }),
+        "guid" => guid_from_bytes(cursor.array()?),
+        "TranslatedString" => {
+            let version = cursor.u16()?;
+            let len = cursor.i32()?.max(0) as usize;
+            let handle = cursor.take(len)?;
+            let handle = handle.strip_suffix(&[0]).unwrap_or(handle);
+            attributes.insert("handle", String::from_utf8_lossy(handle).into_owned());
+            attributes.insert("version", version.to_string());
+            return Ok(Element::new_with_attributes("attribute", attributes).unwrap());
+        }
+        _ => {
+            let text = value.strip_suffix(&[0]).unwrap_or(value);
+            String::from_utf8_lossy(text).into_owned()
+        }
+    };
+
+    attributes.insert("value", text);
+    Ok(Element::new_with_attributes("attribute", attributes).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lsx_string, parse_lsx};
+
+    #[test]
+    fn modsettings_round_trips() {
+        let lsx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="8" revision="0" build="10"/>
+  <region id="ModuleSettings">
+    <node id="root">
+      <children>
+        <node id="Mods">
+          <children>
+            <node id="ModuleShortDesc">
+              <attribute id="Folder" type="LSString" value="GustavX"/>
+              <attribute id="MD5" type="LSString" value=""/>
+              <attribute id="Name" type="LSString" value="GustavX"/>
+              <attribute id="PublishHandle" type="uint64" value="0"/>
+              <attribute id="UUID" type="guid" value="cb555efe-2d9e-131f-8195-a89329d218ea"/>
+              <attribute id="Version64" type="int64" value="36028797018963968"/>
+            </node>
+          </children>
+        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#;
+        let lsf = write(&parse_lsx(lsx.as_bytes()).unwrap()).unwrap();
+
+        assert!(is_lsf(&lsf));
+        assert_eq!(lsx_string(&read(&lsf).unwrap()), lsx);
+    }
+}
@@ -1,512 +1,2279 @@
-use clap::Parser;
-use core::ffi;
-use kiss_xml::{dom::Node, parse_stream};
-use mmap_io::{MemoryMappedFile, MmapMode, segment::Segment};
+use clap::{Parser, Subcommand, ValueEnum};
+use lsxwriter::{
+    CACHE_FILE, CompressionMethod, DEFAULT_PROFILE, ModSettings, Module, ModuleCache,
+    ModuleDescription, Notice, PAKError, PAKFile, ResolveOptions, ScanOptions, SkipReason,
+    VersionPolicy, Warning, apply_order_file, base_version_warnings, check_load_order, dedup_mods,
+    detect_conflicts, diff_mods, duplicate_attribute_warnings, invalid_uuid_warnings,
+    is_valid_guid, json, lsf, lsx_string, open_modules, open_paks, overlay_mods, pak_paths,
+    parse_lsx, read_manifest, read_overrides, regions, resolve, resolve_order, scan_mods,
+    scan_paks, unreadable_paks, version::Version,
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
-    ffi::{CStr, CString},
     fmt::Display,
     fs::{self, File},
-    hash::Hash,
     io::{self, BufWriter, Write},
-    os::linux::raw,
     path::{Path, PathBuf},
-    sync::Arc,
+    process::ExitCode,
+    thread,
 };
-use topologic::AcyclicDependencyGraph;
 
-// The actual game is a "mod" with the prefix "Gustav" (Seems to use both GustavX and GustavDev)
-const BASE_GAME_MOD_PREFIX: &str = "Gustav";
+mod tui;
+
+// Base game versions this tool has been checked against: Patch 7, the first to list GustavX, up
+// to Patch 8
+const TESTED_BASE_VERSIONS: (Version, Version) = (
+    Version::new(4, 1, 1, 5022896),
+    Version::new(4, 1, 1, 6758295),
+);
 
-// Byte order mark (Sometimes included at the start of an XML file)
-const BOM: &str = "\u{feff}";
+// Steam app id of Baldur's Gate 3, which names its Proton prefix
+const STEAM_APP_ID: &str = "1086940";
+
+// How many backups of modsettings.lsx --write keeps
+const BACKUP_COUNT: usize = 5;
 
 #[derive(Parser)]
-#[command(version, about, long_about=None)]
+#[command(version, about, long_about=None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    sort: SortArgs,
+}
+
+#[derive(clap::Args)]
+struct SortArgs {
     #[arg(
         long = "write",
         help = "True to write output modsettings.lsx file, false to output to stdout"
     )]
     write: bool,
-    #[arg(help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3")]
-    game_data: String,
+    #[arg(
+        long = "allow-empty",
+        help = "Allow writing a Mods list that contains nothing besides the base game"
+    )]
+    allow_empty: bool,
+    #[arg(
+        long = "repair",
+        help = "Recreate any missing nodes leading to the Mods list in modsettings.lsx, and the base game module if it isn't listed"
+    )]
+    repair: bool,
+    #[arg(
+        long = "dry-run",
+        help = "Check the computed load order against the rules the game enforces, without writing"
+    )]
+    dry_run: bool,
+    #[arg(
+        long = "resolve-only",
+        conflicts_with = "dry_run",
+        help = "Print the resolved load order in the selected --format and never write any file, even with --write"
+    )]
+    resolve_only: bool,
+    #[arg(
+        long = "plan",
+        conflicts_with_all = ["dry_run", "resolve_only", "write", "format"],
+        help = "Print a summary of the load order and the file it would be written to, instead of the file itself"
+    )]
+    plan: bool,
+    #[arg(
+        long = "backup",
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Copy the file --write replaces to <name>.bak first, keeping older backups as <name>.bak.1, .bak.2, ..."
+    )]
+    backup: bool,
+    #[arg(
+        long = "order-file",
+        help = "File listing mod UUIDs (one per line) in the order to load them. Mods not listed are loaded after, in dependency order"
+    )]
+    order_file: Option<PathBuf>,
+    #[arg(
+        long = "version-check",
+        help = "Print the installed game version and whether this tool has been tested with it, then exit"
+    )]
+    version_check: bool,
+    #[arg(
+        long = "output-format",
+        value_enum,
+        default_value_t = OutputFormat::Lsx,
+        help = "Write modsettings as text (modsettings.lsx) or binary (modsettings.lsf)"
+    )]
+    output_format: OutputFormat,
+    #[arg(
+        long = "sample",
+        value_name = "N",
+        conflicts_with = "write",
+        help = "Only scan the first N paks (by file name) for a quick, partial result"
+    )]
+    sample: Option<usize>,
+    #[arg(
+        long = "explain-skip",
+        help = "List every declared dependency left out of the load order, and why"
+    )]
+    explain_skip: bool,
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = OrderFormat::Lsx,
+        help = "How to print the resolved order: the modsettings document, a Markdown table of the mods, or a JSON array of them"
+    )]
+    format: OrderFormat,
+    #[arg(
+        long = "manifest",
+        help = "File listing the paks to scan (one path per line) instead of the Mods directory"
+    )]
+    manifest: Option<PathBuf>,
+    #[arg(
+        long = "mods-dir",
+        value_name = "DIR",
+        conflicts_with = "manifest",
+        help = "Mods directory to scan instead of the one in the game data directory. Repeat it to combine several, where a mod in a later directory replaces one with the same UUID from an earlier one"
+    )]
+    mods_dirs: Vec<PathBuf>,
+    #[arg(
+        long = "pak",
+        value_name = "PAK",
+        conflicts_with = "manifest",
+        help = "Pak to read instead of scanning a mods directory (repeatable), e.g. to order paks that aren't installed yet. modsettings.lsx is still found in the game data directory"
+    )]
+    paks: Vec<PathBuf>,
+    #[arg(
+        long = "prefer-version",
+        value_enum,
+        help = "Which mod to keep when several paks share a UUID (default highest). existing keeps the version already in modsettings.lsx. Given with several --mods-dir, this applies across them instead of the later directory winning"
+    )]
+    prefer_version: Option<PreferVersion>,
+    #[arg(
+        long = "scan-magic",
+        help = "Look for the pak header past the start of each file, for paks appended to another file"
+    )]
+    scan_magic: bool,
+    #[arg(
+        long = "archive-part-dir",
+        value_name = "DIR",
+        help = "Directory holding the parts (Name_1.pak, ...) of multi part paks, when they aren't next to them"
+    )]
+    archive_part_dir: Option<PathBuf>,
+    #[arg(
+        long = "preserve-uuid-case",
+        help = "Write UUIDs cased the way each mod spells them instead of lowercased"
+    )]
+    preserve_uuid_case: bool,
+    #[arg(
+        long = "preserve-order",
+        help = "Keep mods already in modsettings.lsx in their current order where dependencies allow, adding new ones after them"
+    )]
+    preserve_order: bool,
+    #[arg(
+        long = "profile",
+        default_value = DEFAULT_PROFILE,
+        help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+    )]
+    profile: String,
+    #[arg(
+        long = "diff",
+        help = "Print the mods added, removed and moved compared to the current modsettings.lsx. Without --write, only the differences are printed"
+    )]
+    diff: bool,
+    #[arg(
+        long = "exclude",
+        value_name = "UUID",
+        help = "Leave the mod with this UUID out of the load order (repeatable). Fails if an included mod depends on it"
+    )]
+    exclude: Vec<String>,
+    #[arg(
+        long = "overrides",
+        value_name = "FILE",
+        help = "File of \"UUID = priority\" lines ordering mods that dependencies leave free, higher loads later (unlisted mods are 0)"
+    )]
+    overrides: Option<PathBuf>,
+    #[arg(
+        long = "verify",
+        help = "Check each pak's file list against the MD5 in its header, skipping paks that don't match"
+    )]
+    verify: bool,
+    #[arg(
+        long = "conflicts",
+        help = "Report mods that contain the same files, which the game only loads from one of. Reads every file list in full"
+    )]
+    conflicts: bool,
+    #[arg(
+        long = "no-cache",
+        help = "Read every pak, ignoring the metadata cache in the game data folder. Only runs with --write update the cache"
+    )]
+    no_cache: bool,
+    #[arg(
+        long = "deny-warnings",
+        help = "Still produce the load order when there are warnings or unreadable paks, but exit with status 2"
+    )]
+    deny_warnings: bool,
+    #[arg(
+        long = "jobs",
+        value_name = "N",
+        help = "Open paks and read their metadata on N threads (default one per core). The output is the same as with one"
+    )]
+    jobs: Option<usize>,
+    #[arg(
+        long = "strict",
+        help = "Fail on warnings, and on mods installed more than once, instead of only printing them"
+    )]
+    strict: bool,
+    #[arg(
+        help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3), found in the usual places if not given"
+    )]
+    game_data: Option<String>,
 }
 
-#[derive(Debug)]
-enum PAKError {
-    BadMagic(String),
-    NoMetadata,
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Extract a single file from a pak and print it to stdout")]
+    Extract {
+        #[arg(help = "Pak file to extract from")]
+        pak: PathBuf,
+        #[arg(help = "Path of the file inside the pak (e.g. Mods/Foo/meta.lsx)")]
+        path: String,
+        #[arg(long = "out", help = "File to write to instead of stdout")]
+        out: Option<PathBuf>,
+        #[arg(
+            long = "pretty",
+            help = "Re-indent LSX files so minified ones are readable"
+        )]
+        pretty: bool,
+        #[arg(
+            long = "force-compression",
+            value_enum,
+            help = "Decompress every file with this method, ignoring the (possibly wrong) flags in the pak"
+        )]
+        force_compression: Option<Compression>,
+        #[arg(
+            long = "scan-magic",
+            help = "Look for the pak header past the start of the file, for a pak appended to another file"
+        )]
+        scan_magic: bool,
+        #[arg(
+            long = "archive-part-dir",
+            value_name = "DIR",
+            help = "Directory holding the parts (Name_1.pak, ...) of a multi part pak, when they aren't next to it"
+        )]
+        archive_part_dir: Option<PathBuf>,
+    },
+    #[command(about = "Extract every file in a pak into a directory")]
+    ExtractAll {
+        #[arg(help = "Pak file to extract from")]
+        pak: PathBuf,
+        #[arg(help = "Directory to extract into (created if missing)")]
+        out: PathBuf,
+        #[arg(
+            long = "force-compression",
+            value_enum,
+            help = "Decompress every file with this method, ignoring the (possibly wrong) flags in the pak"
+        )]
+        force_compression: Option<Compression>,
+        #[arg(
+            long = "scan-magic",
+            help = "Look for the pak header past the start of the file, for a pak appended to another file"
+        )]
+        scan_magic: bool,
+        #[arg(
+            long = "archive-part-dir",
+            value_name = "DIR",
+            help = "Directory holding the parts (Name_1.pak, ...) of a multi part pak, when they aren't next to it"
+        )]
+        archive_part_dir: Option<PathBuf>,
+    },
+    #[command(about = "Interactively review and edit the load order before writing it")]
+    Tui {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+        #[arg(
+            long = "profile",
+            default_value = DEFAULT_PROFILE,
+            help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+        )]
+        profile: String,
+    },
+    #[command(about = "Write out the full meta.lsx of a mod pak, normalized")]
+    Convert {
+        #[arg(help = "Pak file to read the metadata from")]
+        pak: PathBuf,
+        #[arg(long = "out", help = "File to write to instead of stdout")]
+        out: Option<PathBuf>,
+    },
+    #[command(about = "List the installed mods, or the files in a pak")]
+    List {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3), or a pak to list the files of"
+        )]
+        path: PathBuf,
+        #[arg(
+            long = "sort-by",
+            value_enum,
+            default_value_t = ListSortKey::Name,
+            help = "How to order the list (this does not affect load order)"
+        )]
+        sort_by: ListSortKey,
+        #[arg(
+            long = "filter",
+            help = "Only list the pak files whose name contains this text"
+        )]
+        filter: Option<String>,
+    },
+    #[command(about = "Show how much disk space each mod takes, largest first")]
+    Sizes {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+    },
+    #[command(about = "Show the metadata of a single mod pak")]
+    Info {
+        #[arg(help = "Pak file to inspect")]
+        pak: PathBuf,
+        #[arg(
+            long = "follow-dependencies",
+            requires = "game_data",
+            help = "Print the full tree of dependencies, resolved against the installed mods"
+        )]
+        follow_dependencies: bool,
+        #[arg(
+            long = "game-data",
+            help = "Directory game data is in, used to find installed dependencies"
+        )]
+        game_data: Option<PathBuf>,
+        #[arg(
+            long = "force-compression",
+            value_enum,
+            help = "Decompress every file with this method, ignoring the (possibly wrong) flags in the pak"
+        )]
+        force_compression: Option<Compression>,
+        #[arg(
+            long = "scan-magic",
+            help = "Look for the pak header past the start of the file, for a pak appended to another file"
+        )]
+        scan_magic: bool,
+        #[arg(
+            long = "archive-part-dir",
+            value_name = "DIR",
+            help = "Directory holding the parts (Name_1.pak, ...) of a multi part pak, when they aren't next to it"
+        )]
+        archive_part_dir: Option<PathBuf>,
+        #[arg(
+            long = "verify",
+            help = "Check the file list against the MD5 in the header first"
+        )]
+        verify: bool,
+    },
+    #[command(
+        about = "Write the resolved load order, then re-read and verify it, restoring the previous modsettings.lsx if it fails"
+    )]
+    Apply {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+        #[arg(
+            long = "allow-empty",
+            help = "Allow writing a Mods list that contains nothing besides the base game"
+        )]
+        allow_empty: bool,
+        #[arg(
+            long = "profile",
+            default_value = DEFAULT_PROFILE,
+            help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+        )]
+        profile: String,
+    },
+    #[command(
+        about = "Check the installed mods and modsettings.lsx for problems the game would trip over"
+    )]
+    Validate {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value_t = ValidateFormat::Text,
+            help = "Print problems as text, or as a SARIF 2.1.0 report for CI code scanning"
+        )]
+        format: ValidateFormat,
+        #[arg(
+            long = "profile",
+            default_value = DEFAULT_PROFILE,
+            help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+        )]
+        profile: String,
+    },
+    #[command(
+        about = "Print the JSON Schemas of the JSON outputs (--format json and validate --format sarif)"
+    )]
+    JsonSchema {
+        #[arg(
+            value_enum,
+            help = "Only print the schema of this output, instead of an object holding both"
+        )]
+        output: Option<JsonOutput>,
+    },
+    #[command(about = "Check a mod pak for mistakes in its meta.lsx, for mod authors")]
+    Lint {
+        #[arg(help = "Pak file to check")]
+        pak: PathBuf,
+    },
+    #[command(
+        about = "Check that every installed mod can be read and its dependencies resolved, without reading or writing modsettings.lsx"
+    )]
+    Check {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+    },
+    #[command(
+        about = "Add a mod to the current load order after its dependencies, leaving the rest of the order as it is"
+    )]
+    Add {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+        #[arg(help = "Pak of the mod to add")]
+        pak: PathBuf,
+        #[arg(long = "write", help = "Write modsettings.lsx instead of printing it")]
+        write: bool,
+        #[arg(
+            long = "profile",
+            default_value = DEFAULT_PROFILE,
+            help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+        )]
+        profile: String,
+    },
+    #[command(
+        about = "Remove a mod from the current load order, along with the mods that depend on it"
+    )]
+    Remove {
+        #[arg(
+            help = "Directory game data is in (.../AppData/Local/Larian Studios/Baldur's Gate 3"
+        )]
+        game_data: PathBuf,
+        #[arg(help = "UUID of the mod to remove")]
+        uuid: String,
+        #[arg(long = "write", help = "Write modsettings.lsx instead of printing it")]
+        write: bool,
+        #[arg(
+            long = "profile",
+            default_value = DEFAULT_PROFILE,
+            help = "Player profile (directory under PlayerProfiles) whose modsettings.lsx to use"
+        )]
+        profile: String,
+    },
 }
 
-impl std::error::Error for PAKError {}
+#[derive(Clone, Copy, ValueEnum)]
+enum ListSortKey {
+    Name,
+    Size,
+    Version,
+    Uuid,
+}
 
-impl Display for PAKError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::BadMagic(magic) => {
-                write!(f, "Bad header magic value: {magic} (should be \"LSPK\")")
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OrderFormat {
+    Lsx,
+    Markdown,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum JsonOutput {
+    // The load order, from --format json
+    Order,
+    // The report of validate --format sarif
+    Validate,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ValidateFormat {
+    Text,
+    Sarif,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Lsx,
+    Lsf,
+}
+
+// VersionPolicy as a command line value
+#[derive(Clone, Copy, ValueEnum)]
+enum PreferVersion {
+    Highest,
+    Lowest,
+    Existing,
+}
+
+impl From<PreferVersion> for VersionPolicy {
+    fn from(policy: PreferVersion) -> Self {
+        match policy {
+            PreferVersion::Highest => Self::Highest,
+            PreferVersion::Lowest => Self::Lowest,
+            PreferVersion::Existing => Self::Existing,
+        }
+    }
+}
+
+// CompressionMethod as a command line value
+#[derive(Clone, Copy, ValueEnum)]
+enum Compression {
+    None,
+    Zlib,
+    Lz4,
+    Zstd,
+}
+
+impl From<Compression> for CompressionMethod {
+    fn from(method: Compression) -> Self {
+        match method {
+            Compression::None => Self::None,
+            Compression::Zlib => Self::Zlib,
+            Compression::Lz4 => Self::Lz4,
+            Compression::Zstd => Self::Zstd,
+        }
+    }
+}
+
+// Exits with 0 on success, 1 on errors and 2 when --deny-warnings rejects a produced load order
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let result = match args.command {
+        Some(Command::Extract {
+            pak,
+            path,
+            out,
+            pretty,
+            force_compression,
+            scan_magic,
+            archive_part_dir,
+        }) => extract(
+            pak,
+            &path,
+            out,
+            pretty,
+            force_compression,
+            scan_magic,
+            archive_part_dir.as_deref(),
+        ),
+        Some(Command::ExtractAll {
+            pak,
+            out,
+            force_compression,
+            scan_magic,
+            archive_part_dir,
+        }) => extract_all(
+            pak,
+            &out,
+            force_compression,
+            scan_magic,
+            archive_part_dir.as_deref(),
+        ),
+        Some(Command::Tui { game_data, profile }) => interactive(game_data, &profile),
+        Some(Command::Convert { pak, out }) => convert(pak, out),
+        Some(Command::List {
+            path,
+            sort_by,
+            filter,
+        }) => {
+            if path.is_file() {
+                list_pak(path, filter.as_deref())
+            } else {
+                list(path, sort_by)
             }
-            Self::NoMetadata => {
-                write!(f, "Could not find meta.lsx file in pak")
+        }
+        Some(Command::Sizes { game_data }) => sizes(game_data),
+        Some(Command::Info {
+            pak,
+            follow_dependencies,
+            game_data,
+            force_compression,
+            scan_magic,
+            archive_part_dir,
+            verify,
+        }) => info(
+            pak,
+            follow_dependencies.then_some(game_data).flatten(),
+            force_compression,
+            scan_magic,
+            archive_part_dir.as_deref(),
+            verify,
+        ),
+        Some(Command::Apply {
+            game_data,
+            allow_empty,
+            profile,
+        }) => apply(game_data, &profile, allow_empty),
+        Some(Command::Validate {
+            game_data,
+            format,
+            profile,
+        }) => validate(game_data, &profile, format),
+        Some(Command::JsonSchema { output }) => {
+            match output {
+                Some(JsonOutput::Order) => println!("{ORDER_SCHEMA}"),
+                Some(JsonOutput::Validate) => println!("{VALIDATE_REPORT_SCHEMA}"),
+                None => println!(
+                    "{{\n  \"order\": {},\n  \"validate\": {}\n}}",
+                    ORDER_SCHEMA.replace('\n', "\n  "),
+                    VALIDATE_REPORT_SCHEMA.replace('\n', "\n  ")
+                ),
             }
+            Ok(())
+        }
+        Some(Command::Lint { pak }) => lint(pak),
+        Some(Command::Check { game_data }) => check(game_data),
+        Some(Command::Add {
+            game_data,
+            pak,
+            write,
+            profile,
+        }) => add(game_data, pak, write, &profile),
+        Some(Command::Remove {
+            game_data,
+            uuid,
+            write,
+            profile,
+        }) => remove(game_data, &uuid, write, &profile),
+        None => sort(args.sort),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is::<DeniedWarnings>() => {
+            eprintln!("{e}");
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
         }
     }
 }
 
-#[derive(Debug)]
-struct PAKFile<'a> {
-    file: Arc<MemoryMappedFile>,
-    header: &'a PAKHeader,
-    _file_list_data: Vec<u8>,
-    file_list: &'a [PAKFileEntry],
+fn extract(
+    pak: PathBuf,
+    path: &str,
+    out: Option<PathBuf>,
+    pretty: bool,
+    force_compression: Option<Compression>,
+    scan_magic: bool,
+    archive_part_dir: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let mut pak = PAKFile::open_with(pak, scan_magic, archive_part_dir)?;
+    pak.force_compression = force_compression.map(Into::into);
+
+    let Some(entry) = pak.find_entry(path) else {
+        let mut names: Vec<String> = pak.file_list.iter().map(|entry| entry.name()).collect();
+        names.sort_by_key(|name| edit_distance(&name.to_lowercase(), &path.to_lowercase()));
+
+        if !names.is_empty() {
+            eprintln!("Closest matches:");
+            for name in names.iter().take(5) {
+                eprintln!("  {name}");
+            }
+        }
+
+        return Err(Box::new(PAKError::NoSuchFile(path.to_string())));
+    };
+
+    let data = pak.read_entry(entry)?;
+    print_notices(&pak.take_notices());
+
+    let mut writer: BufWriter<Box<dyn Write>> = match &out {
+        Some(out) => BufWriter::new(Box::new(File::create(out)?)),
+        None => BufWriter::new(Box::new(io::stdout().lock())),
+    };
+
+    if pretty && path.ends_with(".lsx") {
+        write!(writer, "{}", lsx_string(&parse_lsx(&data)?))?;
+    } else {
+        if pretty {
+            eprintln!("Ignoring --pretty for {path} (not an LSX file)");
+        }
+        writer.write_all(&data)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
 }
 
-impl<'a> Display for PAKFile<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "PAKFile: {}", self.file.path().display())?;
-        writeln!(f, "File Count: {}", self.file_list.len())
+// Levenshtein distance, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
     }
+
+    row[b.len()]
 }
 
-impl<'a> PAKFile<'a> {
-    fn open(path: PathBuf) -> Result<PAKFile<'a>, Box<dyn Error>> {
-        let file = MemoryMappedFile::open_ro(path)?;
-        let header_slice = file.as_slice(0, size_of::<PAKHeader>().try_into()?)?;
-        let header: &PAKHeader = unsafe { std::mem::transmute(header_slice.as_ptr()) };
+// Files keep the time they were extracted at: LSPK 18 entries don't record modification times, so
+// there is nothing to restore
+fn extract_all(
+    pak: PathBuf,
+    out: &Path,
+    force_compression: Option<Compression>,
+    scan_magic: bool,
+    archive_part_dir: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let mut pak = PAKFile::open_with(pak, scan_magic, archive_part_dir)?;
+    pak.force_compression = force_compression.map(Into::into);
+
+    let mut extracted = 0;
+    for entry in &pak.file_list {
+        let name = entry.name();
+
+        // Names come from the pak, don't let one write outside the output directory
+        let relative = Path::new(&name);
+        if !relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            eprintln!("Skipping {name} (not a plain relative path)");
+            continue;
+        }
 
-        if header.magic != *b"LSPK" {
-            return Err(Box::new(PAKError::BadMagic(format!("{:?}", header.magic))));
+        let path = out.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(&path, pak.read_entry(entry)?)?;
+        print_notices(&pak.take_notices());
+        extracted += 1;
+    }
 
-        // Literally why
-        let file_list_info = file
-            .as_slice(header.file_list_offset, size_of::<u32>() as u64 * 2)
-            .unwrap();
+    println!("Extracted {extracted} files to {}", out.display());
 
-        let file_count =
-            u32::from_le_bytes(file_list_info.get(0..4).unwrap().try_into().unwrap()) as usize;
+    Ok(())
+}
+
+// Where the game keeps its data on each platform, most likely first
+fn game_data_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+        candidates.push(PathBuf::from(local).join("Larian Studios/Baldur's Gate 3"));
+    }
+
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        let prefix = format!(
+            "steamapps/compatdata/{STEAM_APP_ID}/pfx/drive_c/users/steamuser/AppData/Local/Larian Studios/Baldur's Gate 3"
+        );
+        for steam in [
+            ".local/share/Steam",
+            ".steam/steam",
+            ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+        ] {
+            candidates.push(home.join(steam).join(&prefix));
+        }
+        candidates.push(home.join("Documents/Larian Studios/Baldur's Gate 3"));
+    }
 
-        let compressed_size =
-            u32::from_le_bytes(file_list_info.get(4..8).unwrap().try_into().unwrap());
+    candidates
+}
 
-        let file_list_slice = file
-            .as_slice(header.file_list_offset + 8, compressed_size as u64)
-            .unwrap();
+fn detect_game_data() -> Result<PathBuf, Box<dyn Error>> {
+    let candidates = game_data_candidates();
 
-        let uncompressed_size = size_of::<PAKFileEntry>() * file_count;
+    match candidates.iter().find(|candidate| candidate.is_dir()) {
+        Some(game_data) => {
+            eprintln!("Using game data directory {}", game_data.display());
+            Ok(game_data.clone())
+        }
+        None => {
+            let searched: Vec<String> = candidates
+                .iter()
+                .map(|candidate| format!("  {}", candidate.display()))
+                .collect();
+            Err(format!(
+                "Could not find the game data directory, pass it as an argument. Looked in:\n{}",
+                searched.join("\n")
+            )
+            .into())
+        }
+    }
+}
 
-        let _file_list_data = lz4_flex::decompress(file_list_slice, uncompressed_size).unwrap();
+// What the library worked around or left out while reading, as warnings
+fn print_notices(notices: &[Notice]) {
+    for notice in notices {
+        eprintln!("{notice}");
+    }
+}
 
-        assert_eq!(
-            file_count,
-            _file_list_data.len() / size_of::<PAKFileEntry>()
+// lsxwriter::modsettings_path, warning when the file found isn't named the way the game names it
+fn modsettings_path(game_data: &Path, profile: &str) -> Result<PathBuf, lsxwriter::Error> {
+    let path = lsxwriter::modsettings_path(game_data, profile)?;
+    if path
+        .file_name()
+        .is_some_and(|name| name != "modsettings.lsx")
+    {
+        eprintln!(
+            "Warning: using {} (the game names it modsettings.lsx)",
+            path.display()
         );
+    }
+    Ok(path)
+}
 
-        let file_list = unsafe {
-            let file_list_ptr: *const PAKFileEntry = std::mem::transmute(_file_list_data.as_ptr());
-            std::slice::from_raw_parts(file_list_ptr, file_count)
+fn sort(args: SortArgs) -> Result<(), Box<dyn Error>> {
+    let game_data = match &args.game_data {
+        Some(game_data) => PathBuf::from(game_data),
+        None => detect_game_data()?,
+    };
+
+    // Markdown and JSON go to stdout on their own, so scripts can read them
+    let structured = args.format != OrderFormat::Lsx;
+    macro_rules! status {
+        ($($arg:tt)*) => {
+            if structured {
+                eprintln!($($arg)*);
+            } else {
+                println!($($arg)*);
+            }
         };
+    }
 
-        Ok(Self {
-            file: Arc::new(file),
-            header,
-            _file_list_data,
-            file_list,
-        })
+    let options = ScanOptions {
+        sample: args.sample,
+        scan_magic: args.scan_magic,
+        jobs: args.jobs.unwrap_or_else(|| {
+            thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        }),
+        part_dir: args.archive_part_dir.as_deref(),
+        verify: args.verify,
+    };
+    let mods_dirs = if args.mods_dirs.is_empty() {
+        vec![game_data.join("Mods")]
+    } else {
+        args.mods_dirs.clone()
+    };
+
+    let mut cache = (!args.no_cache).then(|| ModuleCache::open(game_data.join(CACHE_FILE)));
+
+    // Mods of each mods directory, in the order given
+    let (layers, notices): (Vec<Vec<Module>>, Vec<Vec<Notice>>) = match &args.manifest {
+        _ if !args.paks.is_empty() => [open_modules(args.paks.clone(), options, cache.as_mut())]
+            .into_iter()
+            .unzip(),
+        Some(manifest) => [open_modules(
+            read_manifest(manifest)?,
+            options,
+            cache.as_mut(),
+        )]
+        .into_iter()
+        .unzip(),
+        None => mods_dirs
+            .iter()
+            .map(|mods_dir| Ok(open_modules(pak_paths(mods_dir)?, options, cache.as_mut())))
+            .collect::<Result<Vec<_>, lsxwriter::Error>>()?
+            .into_iter()
+            .unzip(),
+    };
+    let notices: Vec<Notice> = notices.into_iter().flatten().collect();
+    print_notices(&notices);
+    let unreadable = unreadable_paks(&notices);
+
+    // Only runs that write modsettings.lsx update the cache, the others leave the game data as it
+    // is. A cache that can't be written only costs speed on the next run
+    let writes = args.write && !args.resolve_only && !args.dry_run;
+    if let Some(cache) = cache.filter(|cache| writes && cache.is_changed())
+        && let Err(e) = write_atomically(cache.path(), cache.to_json().as_bytes())
+    {
+        eprintln!("Failed to write {}: {e}", cache.path().display());
     }
 
-    fn module(&self) -> Result<Module, Box<dyn Error>> {
-        let Some(metadata) = self.file_list.iter().find(|entry| {
-            let name = entry.name();
-            name.starts_with("Mods/") && name.ends_with("/meta.lsx")
-        }) else {
-            return Err(Box::new(PAKError::NoMetadata));
-        };
+    let modsettings_path = modsettings_path(&game_data, &args.profile)?;
+    let mut modsettings = ModSettings::parse_with(File::open(&modsettings_path)?, args.repair)?;
+    print_notices(modsettings.notices());
 
-        let compressed_meta = self
-            .file
-            .as_slice(metadata.offset(), metadata.size_on_disk as u64)
-            .unwrap();
+    let base_mod = modsettings.base_module()?;
+    status!(
+        "Found base module: {} ({})",
+        base_mod.name,
+        base_mod.display_version()
+    );
 
-        let raw_meta =
-            lz4_flex::decompress(compressed_meta, metadata.uncompressed_size as usize).unwrap();
+    if args.version_check {
+        return version_check(&base_mod);
+    }
 
-        let str_meta = str::from_utf8(&raw_meta).unwrap();
+    let existing = modsettings.mods().to_vec();
+    let (mods, duplicates) = match args.prefer_version {
+        None if layers.len() > 1 => {
+            let (layers, duplicates): (Vec<_>, Vec<_>) = layers
+                .into_iter()
+                .map(|mods| dedup_mods(mods, VersionPolicy::Highest, &existing))
+                .unzip();
+            // A later directory replacing a mod is what several directories are for, so unlike
+            // the duplicates within one it doesn't fail --strict
+            let (mods, replaced) = overlay_mods(layers);
+            for replacement in &replaced {
+                eprintln!("{replacement}");
+            }
+            (mods, duplicates.into_iter().flatten().collect())
+        }
+        policy => dedup_mods(
+            layers.into_iter().flatten().collect(),
+            policy.map_or(VersionPolicy::Highest, Into::into),
+            &existing,
+        ),
+    };
 
-        // Strip byte order mark if it exists
-        let clean_str_meta = if str_meta.starts_with(BOM) {
-            str_meta.get(BOM.len()..).unwrap()
-        } else {
-            str_meta
-        };
+    for duplicate in &duplicates {
+        eprintln!("{duplicate}");
+    }
+    if args.strict && !duplicates.is_empty() {
+        return Err(format!(
+            "{} mod(s) installed more than once, failing because of --strict",
+            duplicates.len()
+        )
+        .into());
+    }
 
-        let xml_meta = kiss_xml::parse_str(clean_str_meta).unwrap();
+    let mods = exclude_mods(mods, &args.exclude)?;
 
-        let children = xml_meta
-            .root_element()
-            .first_element_by_name("region")?
-            .first_element_by_name("node")?
-            .first_element_by_name("children")?;
+    let overrides = args.overrides.as_deref().map(read_overrides).transpose()?;
+    let resolve_options = ResolveOptions {
+        previous: if args.preserve_order { &existing } else { &[] },
+        overrides: overrides.as_ref(),
+    };
+    let (resolved, skipped) = resolve(&mods, &base_mod, resolve_options)?;
 
-        let mod_info = children
-            .child_elements()
-            .find(|child| child.get_attr("id").unwrap() == "ModuleInfo")
-            .unwrap();
+    let warnings: Vec<&Warning> = resolved.iter().flat_map(|m| &m.warnings).collect();
+    for warning in &warnings {
+        status!("Warning: {warning}");
+    }
 
-        let description = ModuleDescription::parse(mod_info);
+    if args.strict && !warnings.is_empty() {
+        return Err(format!("{} warning(s), failing because of --strict", warnings.len()).into());
+    }
 
-        let dependencies = if let Some(dependencies) = children
-            .child_elements()
-            .find(|child| child.get_attr("id").unwrap() == "Dependencies")
-            .unwrap()
-            .first_element_by_name("children")
-            .ok()
-        {
-            dependencies
-                .child_elements()
-                .map(|desc| ModuleDescription::parse(desc))
-                .filter(|dep| {
-                    if dep.name.starts_with(BASE_GAME_MOD_PREFIX) {
-                        println!(
-                            "Skipping dependency of {} on mod {} (base game)",
-                            description.name, dep.name
-                        );
-                        false
-                    } else {
-                        true
-                    }
-                })
-                .collect()
-        } else {
-            Vec::new()
+    let expected = mods
+        .iter()
+        .filter(|module| module.description != base_mod)
+        .count();
+
+    let mut order: Vec<ModuleDescription> = resolved
+        .iter()
+        .map(|resolved| resolved.description.clone())
+        .collect();
+
+    if args.conflicts {
+        // Only the paks the load order uses, not other versions of the same mods
+        let used: Vec<PathBuf> = mods
+            .iter()
+            .filter_map(|module| module.pak.clone())
+            .collect();
+        let (paks, notices) = open_paks(
+            used,
+            ScanOptions {
+                sample: None,
+                ..options
+            },
+        );
+        print_notices(&notices);
+
+        let name = |uuid: &str| {
+            mods.iter()
+                .find(|module| module.description.uuid == uuid)
+                .map_or(uuid.to_string(), |module| module.description.name.clone())
         };
+        for (a, b, files) in detect_conflicts(&paks) {
+            status!(
+                "Conflict: {} and {} both contain {} file(s), the game only loads one of each:",
+                name(&a),
+                name(&b),
+                files.len()
+            );
+            for file in files {
+                status!("    {file}");
+            }
+        }
+    }
 
-        Ok(Module {
-            description,
-            dependencies,
-        })
+    if args.explain_skip {
+        for skip in &skipped {
+            status!("{skip}");
+        }
+    } else if !skipped.is_empty() {
+        status!(
+            "Skipped {} declared dependencies (pass --explain-skip to list them)",
+            skipped.len()
+        );
     }
-}
 
-#[repr(C, packed)]
-#[derive(Debug)]
-struct PAKHeader {
-    magic: [ffi::c_uchar; 4],
-    version: u32,
-    file_list_offset: u64,
-    file_list_size: u32,
-    flags: u8,
-    priority: u8,
-    md5: [ffi::c_char; 16],
-    num_parts: u16,
-}
-
-#[repr(C, packed)]
-#[derive(Debug)]
-struct PAKFileEntry {
-    name: [ffi::c_uchar; 256],
-    offset_in_file_1: u32,
-    offset_in_file_2: u16,
-    archive_part: u8,
-    flags: u8,
-    size_on_disk: u32,
-    uncompressed_size: u32,
-}
-
-impl PAKFileEntry {
-    fn name(&self) -> String {
-        let size = if let Some(terminator) = self.name.iter().position(|c| *c == 0) {
-            terminator
-        } else {
-            self.name.len() - 1
-        };
+    if let Some(order_file) = &args.order_file {
+        let notices;
+        (order, notices) = apply_order_file(order, order_file, &mods, &base_mod)?;
+        print_notices(&notices);
+    }
+
+    let produced = order.iter().filter(|module| **module != base_mod).count();
+
+    if args.dry_run {
+        let violations = check_load_order(&order, &mods, &base_mod);
+        for violation in &violations {
+            status!("{violation}");
+        }
+
+        if !violations.is_empty() {
+            return Err(format!(
+                "The load order breaks {} rule(s) the game enforces",
+                violations.len()
+            )
+            .into());
+        }
 
-        let name_slice = self.name.get(0..size).unwrap();
-        let mut name_vec = Vec::with_capacity(size);
-        name_vec.extend_from_slice(name_slice);
+        status!("The load order of {produced} mods would be accepted by the game");
+        return Ok(());
+    }
+
+    let write = args.write && !args.resolve_only;
 
-        String::from_utf8(name_vec).unwrap()
+    // Writing a list with only the base game in it silently disables every mod
+    if produced == 0 {
+        if write && !args.allow_empty {
+            return Err(empty_order_error(expected, produced));
+        } else if expected == 0 {
+            status!("Warning: no non-base mods found, the load order only holds the base game");
+        }
     }
 
-    fn offset(&self) -> u64 {
-        self.offset_in_file_1 as u64 | ((self.offset_in_file_2 as u64) << 32)
+    if args.preserve_uuid_case {
+        for module in &mut order {
+            module.uuid = module.written_uuid.clone();
+        }
     }
+
+    modsettings.set_mods(order.clone());
+
+    if args.diff {
+        let changes = diff_mods(&existing, &order);
+        if changes.is_empty() {
+            status!("No changes to the load order");
+        }
+        for change in &changes {
+            status!("{change}");
+        }
+        if !write && !args.plan {
+            return Ok(());
+        }
+    }
+
+    let listing = match args.format {
+        OrderFormat::Lsx => None,
+        OrderFormat::Markdown => Some(markdown_table(&order)),
+        OrderFormat::Json => Some(format!("{}\n", json_order(&order))),
+    };
+    if let Some(listing) = listing {
+        print!("{listing}");
+        if !write {
+            return Ok(());
+        }
+    }
+
+    let (output_path, output) = match args.output_format {
+        OutputFormat::Lsx => (modsettings_path, modsettings.to_lsx_string().into_bytes()),
+        OutputFormat::Lsf => {
+            let lsf = lsf::write(modsettings.document())?;
+
+            // Make sure what the game will read is what was computed
+            if regions(&lsf::read(&lsf)?) != regions(modsettings.document()) {
+                return Err(
+                    "modsettings.lsf does not read back the same as the LSX it was \
+                    written from"
+                        .into(),
+                );
+            }
+
+            (modsettings_path.with_extension("lsf"), lsf)
+        }
+    };
+
+    // Everything up to here is what --write does, so the plan matches what would be written
+    if args.plan {
+        status!("Plan: {produced} mods in the load order");
+        for (index, module) in order.iter().enumerate() {
+            status!(
+                "{:>4}. {} ({})",
+                index + 1,
+                module.name,
+                module.display_version()
+            );
+        }
+        status!("Would write {}", output_path.display());
+        return Ok(());
+    }
+
+    if write {
+        if args.backup && output_path.exists() {
+            let backup = backup_file(&output_path)?;
+            status!(
+                "Backed up {} to {}",
+                output_path.display(),
+                backup.display()
+            );
+        }
+        write_atomically(&output_path, &output)?;
+    } else {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&output)?;
+        stdout.flush().unwrap();
+    }
+
+    if args.resolve_only {
+        return Ok(());
+    }
+
+    // Anything left out of the order makes it a partial success
+    if unreadable == 0 && warnings.is_empty() {
+        status!("Success: {produced} mods in the load order");
+        return Ok(());
+    }
+
+    status!(
+        "Finished with problems: {produced} mods in the load order, {unreadable} unreadable pak(s), {} warning(s)",
+        warnings.len()
+    );
+
+    if args.deny_warnings {
+        return Err(Box::new(DeniedWarnings));
+    }
+
+    Ok(())
 }
 
-impl Display for PAKFileEntry {
+// The order was produced, but --deny-warnings turns its problems into a failure (exit status 2,
+// as opposed to 1 for errors that stopped it from being produced)
+#[derive(Debug)]
+struct DeniedWarnings;
+
+impl std::error::Error for DeniedWarnings {}
+
+impl Display for DeniedWarnings {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name())
+        write!(f, "Failing because of --deny-warnings")
+    }
+}
+
+// Drop the mods with the given UUIDs (in any case), unless a remaining mod depends on one of them
+fn exclude_mods(mods: Vec<Module>, exclude: &[String]) -> Result<Vec<Module>, Box<dyn Error>> {
+    let exclude: Vec<String> = exclude
+        .iter()
+        .map(|uuid| uuid.to_ascii_lowercase())
+        .collect();
+    let (excluded, mods): (Vec<Module>, Vec<Module>) = mods
+        .into_iter()
+        .partition(|module| exclude.contains(&module.description.uuid));
+
+    let needed: Vec<String> = mods
+        .iter()
+        .flat_map(|module| {
+            module
+                .dependencies
+                .iter()
+                .filter(|dependency| excluded.iter().any(|m| m.description == **dependency))
+                .map(|dependency| {
+                    format!(
+                        "{} depends on {} ({})",
+                        module.description.name, dependency.name, dependency.uuid
+                    )
+                })
+        })
+        .collect();
+
+    if !needed.is_empty() {
+        return Err(format!("Can't exclude mods others need:\n{}", needed.join("\n")).into());
     }
+
+    Ok(mods)
 }
 
-#[derive(Debug, Default)]
-struct Module {
-    description: ModuleDescription,
-    dependencies: Vec<ModuleDescription>,
+// Copy path to path.bak, first moving older backups along to .bak.1, .bak.2, ... and dropping the
+// oldest past BACKUP_COUNT
+fn backup_file(path: &Path) -> io::Result<PathBuf> {
+    let backup = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".bak");
+        if n > 0 {
+            name.push(format!(".{n}"));
+        }
+        PathBuf::from(name)
+    };
+
+    for n in (0..BACKUP_COUNT - 1).rev() {
+        if backup(n).exists() {
+            fs::rename(backup(n), backup(n + 1))?;
+        }
+    }
+
+    fs::copy(path, backup(0))?;
+    Ok(backup(0))
 }
 
-#[derive(Debug, Default, Eq, Clone)]
-struct ModuleDescription {
-    folder: String,
-    md5: String,
-    name: String,
-    publish_handle: Option<String>,
-    uuid: String,
-    version64: String,
+// Write data to a temporary file next to path and rename it over path, so a write that is cut off
+// leaves the previous file in place instead of a truncated one. rename replaces an existing file on
+// Windows too
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    let temp = PathBuf::from(name);
+
+    let result = File::create(&temp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = result.and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    Ok(())
 }
 
-impl Hash for ModuleDescription {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.uuid.hash(state);
+// Why a load order with nothing but the base game in it is not written
+fn empty_order_error(expected: usize, produced: usize) -> Box<dyn Error> {
+    if expected == 0 {
+        "No non-base mods found, refusing to write a Mods list with only the base game. \
+        Pass --allow-empty to write it anyway"
+            .into()
+    } else {
+        format!(
+            "Refusing to write a Mods list with no mods besides the base game \
+            (expected {expected}, produced {produced}). Pass --allow-empty to write it anyway"
+        )
+        .into()
     }
 }
 
-impl PartialEq for ModuleDescription {
-    fn eq(&self, other: &Self) -> bool {
-        self.uuid == other.uuid
+fn markdown_table(order: &[ModuleDescription]) -> String {
+    let mut table = String::from("| # | Name | Version | UUID |\n|---|---|---|---|\n");
+
+    for (index, module) in order.iter().enumerate() {
+        let version = module.display_version();
+        table.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            index + 1,
+            markdown_escape(&module.name),
+            markdown_escape(&version),
+            markdown_escape(&module.uuid)
+        ));
     }
+
+    table
 }
 
-impl ModuleDescription {
-    fn parse(mod_element: &kiss_xml::dom::Element) -> Self {
-        let folder = Self::get_attr(mod_element, "Folder").unwrap();
-        let md5 = Self::get_attr(mod_element, "MD5").unwrap();
-        let name = Self::get_attr(mod_element, "Name").unwrap();
-        let publish_handle = Self::get_attr(mod_element, "PublishHandle");
-        let uuid = Self::get_attr(mod_element, "UUID").unwrap();
-        let version64 = Self::get_attr(mod_element, "Version64").unwrap();
+// The order as a JSON array of the fields modsettings.lsx lists for each mod. Update
+// ORDER_SCHEMA along with it
+fn json_order(order: &[ModuleDescription]) -> String {
+    let modules: Vec<String> = order
+        .iter()
+        .map(|module| {
+            format!(
+                "{{\"folder\": {}, \"name\": {}, \"uuid\": {}, \"version64\": {}, \"publish_handle\": {}}}",
+                json::string(&module.folder),
+                json::string(&module.name),
+                json::string(&module.uuid),
+                json::string(&module.version64),
+                module
+                    .publish_handle
+                    .as_deref()
+                    .map_or_else(|| String::from("null"), json::string)
+            )
+        })
+        .collect();
+
+    format!("[{}]", modules.join(", "))
+}
 
-        Self {
-            folder,
-            md5,
-            name,
-            publish_handle,
-            uuid,
-            version64,
+// Backslash escape anything Markdown would treat as formatting, including the table's own pipes
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>|~#".contains(c) {
+            escaped.push('\\');
         }
+        escaped.push(c);
     }
+    escaped
+}
 
-    fn get_attr(mod_element: &kiss_xml::dom::Element, name: &str) -> Option<String> {
-        let Some(att) = mod_element
-            .child_elements()
-            .find(|att| match att.get_attr("id") {
-                Some(id) => id == name,
-                None => false,
-            })
-        else {
-            return None;
-        };
+fn list(game_data: PathBuf, sort_by: ListSortKey) -> Result<(), Box<dyn Error>> {
+    let (mods, notices) = scan_mods(&game_data.join("Mods"), ScanOptions::default())?;
+    print_notices(&notices);
+
+    let mut mods: Vec<(Module, u64)> = mods
+        .into_iter()
+        .map(|module| {
+            let size = module
+                .pak
+                .as_ref()
+                .and_then(|pak| fs::metadata(pak).ok())
+                .map_or(0, |metadata| metadata.len());
+            (module, size)
+        })
+        .collect();
 
-        let Some(val) = att.get_attr("value") else {
-            return None;
-        };
+    // Sort by name first so ties in the other keys come out in a stable order
+    mods.sort_by_key(|(module, _)| module.description.name.to_lowercase());
 
-        Some(val.to_string())
-    }
-
-    fn as_xml(&self) -> kiss_xml::dom::Element {
-        let mut elem = kiss_xml::dom::Element::new_from_name("node").unwrap();
-
-        elem.set_attr("id", "ModuleShortDesc").unwrap();
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "Folder"),
-                    ("type", "LSString"),
-                    ("value", &self.folder),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
-        );
+    match sort_by {
+        ListSortKey::Name => {}
+        ListSortKey::Size => mods.sort_by_key(|(_, size)| *size),
+        ListSortKey::Version => {
+            mods.sort_by_key(|(module, _)| module.description.version64.parse::<i64>().ok())
+        }
+        ListSortKey::Uuid => mods.sort_by_key(|(module, _)| module.description.uuid.clone()),
+    }
 
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "MD5"),
-                    ("type", "LSString"),
-                    ("value", &self.md5),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
+    for (module, size) in &mods {
+        println!(
+            "{}\t{}\t{}\t{size}\t{}",
+            module.description.name,
+            module.description.display_version(),
+            module.description.uuid,
+            module.description.source()
         );
+    }
 
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "Name"),
-                    ("type", "LSString"),
-                    ("value", &self.name),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
-        );
+    Ok(())
+}
 
-        let publish_handle_str = if let Some(publish_handle) = &self.publish_handle {
-            publish_handle.clone()
-        } else {
-            String::from("0")
-        };
+fn list_pak(pak: PathBuf, filter: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let pak = PAKFile::open(pak)?;
 
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "PublishHandle"),
-                    ("type", "uint64"),
-                    ("value", &publish_handle_str),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
-        );
+    let mut entries: Vec<_> = pak
+        .file_list
+        .iter()
+        .filter(|entry| filter.is_none_or(|filter| entry.name().contains(filter)))
+        .collect();
+    entries.sort_by_key(|entry| entry.name());
 
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "UUID"),
-                    ("type", "guid"),
-                    ("value", &self.uuid),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
+    for entry in entries {
+        let method = match entry.compression_method() {
+            Ok(method) => format!("{method:?}").to_lowercase(),
+            Err(_) => format!("unknown ({})", entry.flags & 0x0f),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{method}",
+            entry.name(),
+            entry.offset(),
+            entry.size_on_disk,
+            entry.uncompressed_size,
+            entry.archive_part
         );
+    }
 
-        elem.append(
-            kiss_xml::dom::Element::new(
-                "attribute",
-                None,
-                Some(HashMap::from([
-                    ("id", "Version64"),
-                    ("type", "int64"),
-                    ("value", &self.version64),
-                ])),
-                None,
-                None,
-                None,
-            )
-            .unwrap(),
-        );
+    Ok(())
+}
+
+fn sizes(game_data: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut sizes: Vec<(String, u64, u64)> = Vec::new();
+
+    let (paks, notices) = scan_paks(&game_data.join("Mods"), ScanOptions::default())?;
+    print_notices(&notices);
+
+    for pak in paks {
+        let name = match pak.module() {
+            Ok(module) => module.description.name,
+            Err(_) => pak.path().display().to_string(),
+        };
+        print_notices(&pak.take_notices());
+
+        let on_disk = fs::metadata(pak.path())?.len();
+        sizes.push((name, on_disk, pak.stats().uncompressed_size));
+    }
+
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
 
-        elem
+    println!("On disk\tUncompressed\tName");
+    for (name, on_disk, uncompressed) in &sizes {
+        println!("{on_disk}\t{uncompressed}\t{name}");
     }
+
+    let total_on_disk: u64 = sizes.iter().map(|(_, on_disk, _)| on_disk).sum();
+    let total_uncompressed: u64 = sizes.iter().map(|(_, _, uncompressed)| uncompressed).sum();
+    println!(
+        "{total_on_disk}\t{total_uncompressed}\tTotal ({} mods)",
+        sizes.len()
+    );
+
+    Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+fn convert(pak: PathBuf, out: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let pak = PAKFile::open(pak)?;
+    let module = pak.module();
+    print_notices(&pak.take_notices());
+    let module = module?;
+
+    let mut writer: BufWriter<Box<dyn Write>> = match out {
+        Some(out) => BufWriter::new(Box::new(File::create(out)?)),
+        None => BufWriter::new(Box::new(io::stdout().lock())),
+    };
+
+    write!(writer, "{}", lsx_string(&module.as_meta_xml()))?;
+    writer.flush()?;
 
-    let game_data = PathBuf::from(args.game_data);
-    let mods_dir = game_data.join("Mods");
+    Ok(())
+}
 
-    let mods: Vec<PAKFile> = fs::read_dir(mods_dir)?
-        .filter_map(|path| {
-            let path = match path {
-                Ok(path) => path,
-                Err(_) => return None,
+// Print a mod's metadata. With a game data directory, also print its full dependency tree
+fn info(
+    pak: PathBuf,
+    game_data: Option<PathBuf>,
+    force_compression: Option<Compression>,
+    scan_magic: bool,
+    archive_part_dir: Option<&Path>,
+    verify: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut pak = PAKFile::open_with(pak, scan_magic, archive_part_dir)?;
+    if verify {
+        pak.verify_checksum()?;
+    }
+    pak.force_compression = force_compression.map(Into::into);
+    let module = pak.module();
+    print_notices(&pak.take_notices());
+    let module = module?;
+    let description = &module.description;
+
+    print!("{pak}");
+    println!("Mod Folders: {}", pak.mod_folders().join(", "));
+    println!("Name: {}", description.name);
+    println!("Folder: {}", description.folder);
+    println!("UUID: {}", description.uuid);
+    println!(
+        "Version64: {} ({})",
+        description.version64,
+        description.display_version()
+    );
+    if let Some(build) = &description.build {
+        println!("Build: {build}");
+    }
+    if let Some(author) = &description.author {
+        println!("Author: {author}");
+    }
+    if let Some(text) = &description.description {
+        println!("Description: {text}");
+    }
+    let mut translated: Vec<_> = description.translated.iter().collect();
+    translated.sort_by_key(|(id, _)| *id);
+    for (id, text) in translated {
+        match &text.version {
+            Some(version) => println!("{id}: handle {} (version {version})", text.handle),
+            None => println!("{id}: handle {}", text.handle),
+        }
+    }
+
+    let Some(game_data) = game_data else {
+        println!("Dependencies:");
+        for dependency in &module.dependencies {
+            let base = if dependency.is_base_game() {
+                " [base game]"
+            } else {
+                ""
             };
+            println!("  {} ({}){base}", dependency.name, dependency.uuid);
+        }
+        return Ok(());
+    };
+
+    let (mut installed, notices) = scan_mods(&game_data.join("Mods"), ScanOptions::default())?;
+    print_notices(&notices);
+    if !installed
+        .iter()
+        .any(|other| other.description == *description)
+    {
+        installed.push(Module {
+            description: description.clone(),
+            dependencies: module.dependencies.clone(),
+            ..Default::default()
+        });
+    }
 
-            if let Some(name) = path.file_name().to_str()
-                && name.ends_with(".pak")
+    let by_uuid: HashMap<&str, &Module> = installed
+        .iter()
+        .map(|module| (module.description.uuid.as_str(), module))
+        .collect();
+
+    let mut seen = HashSet::new();
+
+    println!("Dependency tree:");
+    print_dependency_tree(
+        &by_uuid,
+        &module.dependencies,
+        1,
+        &mut Vec::new(),
+        &mut seen,
+    );
+    println!("Total dependencies (including transitive): {}", seen.len());
+
+    Ok(())
+}
+
+// A mistake in a mod's own metadata, as opposed to a problem with the load order
+enum Lint {
+    FolderMismatch { folder: String, meta_folder: String },
+    MissingMd5,
+    InvalidUuid(String),
+    EmptyField(&'static str),
+    OutsideFolder { folder: String, file: String },
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FolderMismatch {
+                folder,
+                meta_folder,
+            } => write!(
+                f,
+                "Folder is \"{folder}\" but meta.lsx is in Mods/{meta_folder}/"
+            ),
+            Self::MissingMd5 => write!(f, "MD5 is empty"),
+            Self::InvalidUuid(uuid) => write!(f, "UUID is not a valid GUID: {uuid}"),
+            Self::EmptyField(id) => write!(f, "{id} is empty"),
+            Self::OutsideFolder { folder, file } => {
+                write!(f, "{file} is outside the mod folder {folder}")
+            }
+        }
+    }
+}
+
+impl Lint {
+    fn suggestion(&self) -> String {
+        match self {
+            Self::FolderMismatch { meta_folder, .. } => {
+                format!("set Folder to \"{meta_folder}\" or move meta.lsx to match")
+            }
+            Self::MissingMd5 => String::from("fill in the MD5 of the pak when publishing"),
+            Self::InvalidUuid(_) => {
+                String::from("generate a new GUID in the 8-4-4-4-12 hex digit format")
+            }
+            Self::EmptyField(id) => format!("give {id} a value"),
+            Self::OutsideFolder { folder, .. } => {
+                format!("move it under a {folder}/ directory (e.g. Public/{folder}/)")
+            }
+        }
+    }
+}
+
+fn lint(pak: PathBuf) -> Result<(), Box<dyn Error>> {
+    let pak = PAKFile::open(pak)?;
+    let module = pak.module();
+    print_notices(&pak.take_notices());
+    let module = module?;
+    let description = &module.description;
+
+    let mut lints = Vec::new();
+
+    // module() found this entry, so the path has a folder between Mods/ and meta.lsx (or .lsf)
+    if let Some(meta_folder) = pak.metadata_entry()?.and_then(|entry| {
+        let name = entry.name();
+        let folder = name.strip_prefix("Mods/")?;
+        folder
+            .strip_suffix("/meta.lsx")
+            .or_else(|| folder.strip_suffix("/meta.lsf"))
+            .map(str::to_string)
+    }) && meta_folder != description.folder
+    {
+        lints.push(Lint::FolderMismatch {
+            folder: description.folder.clone(),
+            meta_folder,
+        });
+    }
+
+    for (id, value) in [
+        ("Folder", &description.folder),
+        ("Name", &description.name),
+        ("UUID", &description.uuid),
+        ("Version64", &description.version64),
+    ] {
+        if value.trim().is_empty() {
+            lints.push(Lint::EmptyField(id));
+        }
+    }
+
+    if description.md5.trim().is_empty() {
+        lints.push(Lint::MissingMd5);
+    }
+
+    if !description.uuid.trim().is_empty() && !is_valid_guid(&description.uuid) {
+        lints.push(Lint::InvalidUuid(description.uuid.clone()));
+    }
+
+    // The game looks for a mod's files in directories named after its folder (Mods/<folder>,
+    // Public/<folder>, ...), localization is the only shared directory. meta.lsx itself is covered
+    // by the folder mismatch lint
+    if !description.folder.is_empty() {
+        for entry in &pak.file_list {
+            let name = entry.name();
+            if !name.starts_with("Localization/")
+                && !name.ends_with("/meta.lsx")
+                && !name.ends_with("/meta.lsf")
+                && !name.split('/').any(|part| part == description.folder)
             {
-                match PAKFile::open(path.path()) {
-                    Ok(module) => return Some(module),
-                    Err(e) => eprintln!("Failed to parse pak file: {e}"),
-                };
+                lints.push(Lint::OutsideFolder {
+                    folder: description.folder.clone(),
+                    file: name.to_string(),
+                });
             }
+        }
+    }
 
-            None
-        })
+    for lint in &lints {
+        println!("{lint}\n  Fix: {}", lint.suggestion());
+    }
+
+    if !lints.is_empty() {
+        return Err(format!("{} lint(s) in {}", lints.len(), pak.path().display()).into());
+    }
+
+    println!("No lints in {}", pak.path().display());
+
+    Ok(())
+}
+
+fn print_dependency_tree(
+    by_uuid: &HashMap<&str, &Module>,
+    dependencies: &[ModuleDescription],
+    depth: usize,
+    path: &mut Vec<String>,
+    seen: &mut HashSet<String>,
+) {
+    let indent = "  ".repeat(depth);
+
+    for dependency in dependencies {
+        if dependency.is_base_game() {
+            println!(
+                "{indent}{} ({}) [base game]",
+                dependency.name, dependency.uuid
+            );
+            continue;
+        }
+
+        seen.insert(dependency.uuid.clone());
+
+        let Some(installed) = by_uuid.get(dependency.uuid.as_str()) else {
+            println!(
+                "{indent}{} ({}) [missing]",
+                dependency.name, dependency.uuid
+            );
+            continue;
+        };
+
+        if path.contains(&dependency.uuid) {
+            println!("{indent}{} ({}) [cycle]", dependency.name, dependency.uuid);
+            continue;
+        }
+
+        println!("{indent}{} ({})", dependency.name, dependency.uuid);
+
+        path.push(dependency.uuid.clone());
+        print_dependency_tree(by_uuid, &installed.dependencies, depth + 1, path, seen);
+        path.pop();
+    }
+}
+
+// The mods in the Mods directory, one per UUID (the highest version), reporting any left out
+fn installed_mods(game_data: &Path) -> Result<Vec<Module>, Box<dyn Error>> {
+    let (mods, notices) = scan_mods(&game_data.join("Mods"), ScanOptions::default())?;
+    print_notices(&notices);
+
+    let (mods, duplicates) = dedup_mods(mods, VersionPolicy::Highest, &[]);
+    for duplicate in &duplicates {
+        eprintln!("{duplicate}");
+    }
+    Ok(mods)
+}
+
+fn interactive(game_data: PathBuf, profile: &str) -> Result<(), Box<dyn Error>> {
+    let mods = installed_mods(&game_data)?;
+
+    let modsettings_path = modsettings_path(&game_data, profile)?;
+    let mut modsettings = ModSettings::parse(File::open(&modsettings_path)?)?;
+    print_notices(modsettings.notices());
+
+    let base_mod = modsettings.base_module()?;
+
+    let (resolved, _) = resolve(&mods, &base_mod, ResolveOptions::default())?;
+
+    let Some(order) = tui::run(&mods, resolved, &base_mod)? else {
+        println!("Quit without writing");
+        return Ok(());
+    };
+
+    modsettings.set_mods(order);
+
+    save_modsettings(&modsettings, &modsettings_path, true)
+}
+
+// Put the mod as late in the current order as it can go: at the end, or right before the first
+// listed mod that depends on it, which has to be after its own dependencies. A mod already listed
+// is updated in place if its position works
+fn add(game_data: PathBuf, pak: PathBuf, write: bool, profile: &str) -> Result<(), Box<dyn Error>> {
+    let pak = PAKFile::open(pak)?;
+    let module = pak.module();
+    print_notices(&pak.take_notices());
+    let module = module?;
+    let mods = installed_mods(&game_data)?;
+
+    let modsettings_path = modsettings_path(&game_data, profile)?;
+    let mut modsettings = ModSettings::parse(File::open(&modsettings_path)?)?;
+    print_notices(modsettings.notices());
+    let base_mod = modsettings.base_module()?;
+    let order = modsettings.mods();
+
+    let position = |uuid: &str| order.iter().position(|listed| listed.uuid == uuid);
+    let missing: Vec<&ModuleDescription> = module
+        .dependencies
+        .iter()
+        .filter(|dependency| !dependency.is_base_game() && position(&dependency.uuid).is_none())
+        .collect();
+    if !missing.is_empty() {
+        let names: Vec<&str> = missing.iter().map(|module| module.name.as_str()).collect();
+        return Err(format!(
+            "{} depends on mods that aren't in the load order: {}",
+            module.description.name,
+            names.join(", ")
+        )
+        .into());
+    }
+
+    let listed = position(&module.description.uuid);
+    let after = module
+        .dependencies
+        .iter()
+        .chain([&base_mod])
+        .filter_map(|dependency| position(&dependency.uuid))
+        .max()
+        .map_or(0, |index| index + 1);
+    let before = mods
+        .iter()
+        .filter(|installed| installed.dependencies.contains(&module.description))
+        .filter_map(|installed| position(&installed.description.uuid))
+        .min()
+        .unwrap_or(order.len());
+
+    if after > before {
+        return Err(format!(
+            "{} has to load after {} and before {}, which the current order doesn't allow. Regenerate the whole order instead",
+            module.description.name, order[after - 1].name, order[before].name
+        )
+        .into());
+    }
+
+    let name = module.description.name.clone();
+    let uuid = module.description.uuid.clone();
+    modsettings.add(module.description);
+    let index = match listed {
+        Some(index) if (after..=before).contains(&index) => index,
+        // Moving it out of the way shifts everything after it up by one
+        Some(index) if index < after => after - 1,
+        _ => before,
+    };
+    modsettings.move_to(&uuid, index);
+    eprintln!("Added {name} at position {}", index + 1);
+
+    save_modsettings(&modsettings, &modsettings_path, write)
+}
+
+// Drop the mod, and every listed mod that (directly or through others) depends on it, since the
+// game would refuse to load those
+fn remove(
+    game_data: PathBuf,
+    uuid: &str,
+    write: bool,
+    profile: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mods = installed_mods(&game_data)?;
+
+    let modsettings_path = modsettings_path(&game_data, profile)?;
+    let mut modsettings = ModSettings::parse(File::open(&modsettings_path)?)?;
+    print_notices(modsettings.notices());
+
+    // Everything depends on the base game, so removing it would take every mod with it and leave a
+    // list the game rejects
+    let base_mod = modsettings.base_module().ok();
+    if let Some(module) = modsettings.mods().iter().find(|listed| {
+        listed.uuid.eq_ignore_ascii_case(uuid)
+            && (listed.is_base_game() || base_mod.as_ref() == Some(*listed))
+    }) {
+        return Err(format!("{} is part of the game and can't be removed", module.name).into());
+    }
+
+    let Some(module) = modsettings.remove(uuid) else {
+        return Err(format!("No mod with UUID {uuid} in the load order").into());
+    };
+    eprintln!("Removed {}", module.name);
+
+    let mut removed = vec![module];
+    loop {
+        let dependent = modsettings.mods().iter().find(|listed| {
+            mods.iter().any(|installed| {
+                installed.description == **listed
+                    && installed
+                        .dependencies
+                        .iter()
+                        .any(|dependency| removed.contains(dependency))
+            })
+        });
+        let Some(dependent) = dependent.map(|listed| listed.uuid.clone()) else {
+            break;
+        };
+
+        if let Some(module) = modsettings.remove(&dependent) {
+            eprintln!("Removed {}, which depends on a removed mod", module.name);
+            removed.push(module);
+        }
+    }
+
+    save_modsettings(&modsettings, &modsettings_path, write)
+}
+
+// Write modsettings.lsx (keeping a backup), or print it without write
+fn save_modsettings(
+    modsettings: &ModSettings,
+    path: &Path,
+    write: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !write {
+        print!("{}", modsettings.to_lsx_string());
+        return Ok(());
+    }
+
+    if path.exists() {
+        let backup = backup_file(path)?;
+        println!("Backed up {} to {}", path.display(), backup.display());
+    }
+    write_atomically(path, modsettings.to_lsx_string().as_bytes())?;
+    println!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+// Write the resolved order, then read modsettings.lsx back and check it the way the game would,
+// putting the previous file back if it doesn't hold up
+fn apply(game_data: PathBuf, profile: &str, allow_empty: bool) -> Result<(), Box<dyn Error>> {
+    let mods = installed_mods(&game_data)?;
+
+    let modsettings_path = modsettings_path(&game_data, profile)?;
+    let mut modsettings = ModSettings::parse(File::open(&modsettings_path)?)?;
+    print_notices(modsettings.notices());
+
+    let base_mod = modsettings.base_module()?;
+
+    let (order, _) = resolve_order(&mods, &base_mod, ResolveOptions::default())?;
+    let produced = order.iter().filter(|module| **module != base_mod).count();
+
+    if produced == 0 && !allow_empty {
+        let expected = mods
+            .iter()
+            .filter(|module| module.description != base_mod)
+            .count();
+        return Err(empty_order_error(expected, produced));
+    }
+
+    // On disk rather than in memory, so there's still something to roll back to if this is cut off
+    // before verifying
+    let backup = backup_file(&modsettings_path)?;
+    println!(
+        "Backed up {} to {}",
+        modsettings_path.display(),
+        backup.display()
+    );
+
+    modsettings.set_mods(order);
+    write_atomically(&modsettings_path, modsettings.to_lsx_string().as_bytes())?;
+
+    if let Err(e) = verify_modsettings(&modsettings_path, &mods, &base_mod) {
+        write_atomically(&modsettings_path, &fs::read(&backup)?)?;
+        return Err(format!(
+            "The written modsettings.lsx failed verification, restored the previous one from {}: {e}",
+            backup.display()
+        )
+        .into());
+    }
+
+    println!("Applied and verified a load order of {produced} mods");
+
+    Ok(())
+}
+
+fn verify_modsettings(
+    modsettings_path: &Path,
+    mods: &[Module],
+    base_mod: &ModuleDescription,
+) -> Result<(), Box<dyn Error>> {
+    let modsettings = ModSettings::parse(File::open(modsettings_path)?)?;
+    print_notices(modsettings.notices());
+
+    let violations: Vec<String> = check_load_order(modsettings.mods(), mods, base_mod)
+        .iter()
+        .map(ToString::to_string)
         .collect();
 
-    let mods: Vec<Module> = mods.iter().map(|pak| pak.module().unwrap()).collect();
-
-    let modsettings_path = game_data.join("PlayerProfiles/Public/modsettings.lsx");
-    let mut modsettings = kiss_xml::parse_stream(File::open(&modsettings_path)?).unwrap();
-
-    let modlist = modsettings
-        .root_element_mut()
-        .first_element_by_name_mut("region")?
-        .first_element_by_name_mut("node")?
-        .first_element_by_name_mut("children")?
-        .child_elements_mut()
-        .find(|child| child.get_attr("id").unwrap() == "Mods")
-        .unwrap()
-        .first_element_by_name_mut("children")
-        .unwrap();
-
-    let base_elem = modlist
-        .child_elements()
-        .find(|old_mod| {
-            let name = ModuleDescription::get_attr(old_mod, "Name").unwrap();
-
-            // The actual game is a mod that starts with gustav (GustavX or GustavDev. Difference is???)
-            name.starts_with(BASE_GAME_MOD_PREFIX)
+    if !violations.is_empty() {
+        return Err(violations.join("; ").into());
+    }
+
+    Ok(())
+}
+
+// Something validate found, with where it was found
+struct Problem {
+    rule_id: &'static str,
+    error: bool,
+    message: String,
+    // Pak (or modsettings.lsx) the problem is in, and the UUID of the mod at fault
+    path: PathBuf,
+    uuid: Option<String>,
+}
+
+// Check the installed mods and the current modsettings.lsx order without changing anything
+fn validate(
+    game_data: PathBuf,
+    profile: &str,
+    format: ValidateFormat,
+) -> Result<(), Box<dyn Error>> {
+    let mods = installed_mods(&game_data)?;
+
+    let modsettings_path = modsettings_path(&game_data, profile)?;
+    let modsettings = ModSettings::parse(File::open(&modsettings_path)?)?;
+    print_notices(modsettings.notices());
+
+    let base_mod = modsettings.base_module()?;
+    let order = modsettings.mods();
+
+    let problem = |rule_id, error, message, module_uuid: Option<&str>| {
+        let module = module_uuid.and_then(|uuid| {
+            mods.iter()
+                .find(|m| m.description.uuid.eq_ignore_ascii_case(uuid))
+        });
+        Problem {
+            rule_id,
+            error,
+            message,
+            path: module
+                .and_then(|module| module.pak.clone())
+                .unwrap_or_else(|| modsettings_path.clone()),
+            uuid: module.map(|module| module.description.uuid.clone()),
+        }
+    };
+
+    let mut warnings = invalid_uuid_warnings(&mods);
+    warnings.extend(duplicate_attribute_warnings(&mods));
+    warnings.extend(base_version_warnings(&mods, &base_mod));
+
+    let mut problems: Vec<Problem> = warnings
+        .iter()
+        .map(|warning| {
+            problem(
+                warning.rule_id(),
+                false,
+                warning.to_string(),
+                warning.module_uuid(),
+            )
         })
-        .unwrap();
+        .collect();
 
-    let base_mod = ModuleDescription::parse(base_elem);
-    println!("Found base module: {}", base_mod.name);
+    problems.extend(
+        check_load_order(order, &mods, &base_mod)
+            .iter()
+            .map(|violation| {
+                problem(
+                    violation.rule_id(),
+                    true,
+                    violation.to_string(),
+                    violation.module_uuid(),
+                )
+            }),
+    );
+
+    match format {
+        ValidateFormat::Text => {
+            for problem in &problems {
+                let level = if problem.error { "Error" } else { "Warning" };
+                println!("{level}: {} ({})", problem.message, problem.path.display());
+            }
+        }
+        ValidateFormat::Sarif => println!("{}", sarif_report(&problems)),
+    }
 
-    let mut new_modlist = modlist.clone();
+    if problems.iter().any(|problem| problem.error) {
+        return Err(format!("Found {} problem(s)", problems.len()).into());
+    }
 
-    // Rest mod list
-    new_modlist.remove_elements_by_name("node");
+    if format == ValidateFormat::Text && problems.is_empty() {
+        println!("No problems found in {} mods", mods.len());
+    }
 
-    let mut dep_graph = AcyclicDependencyGraph::new();
-    for module in mods {
-        dep_graph
-            .depend_on(module.description.clone(), base_mod.clone())
-            .unwrap();
-        for dependency in module.dependencies {
-            dep_graph
-                .depend_on(module.description.clone(), dependency)
-                .unwrap();
+    Ok(())
+}
+
+// Reads every pak and resolves the dependencies of the mods in them. Unlike validate, this doesn't
+// need a modsettings.lsx, so the base game only stands in for dependencies on it
+fn check(game_data: PathBuf) -> Result<(), Box<dyn Error>> {
+    let (paks, notices) = scan_paks(&game_data.join("Mods"), ScanOptions::default())?;
+    print_notices(&notices);
+    let mut problems = unreadable_paks(&notices);
+
+    let mut mods = Vec::new();
+    for pak in paks.iter().filter(|pak| pak.is_module()) {
+        let module = pak.module();
+        print_notices(&pak.take_notices());
+        match module {
+            Ok(module) => mods.push(module),
+            Err(e) => {
+                println!(
+                    "Failed to read the metadata of {}: {e}",
+                    pak.path().display()
+                );
+                problems += 1;
+            }
         }
     }
+    let (mods, duplicates) = dedup_mods(mods, VersionPolicy::Highest, &[]);
+    for duplicate in &duplicates {
+        eprintln!("{duplicate}");
+    }
 
-    for layer in dep_graph.get_forward_dependency_topological_layers() {
-        for module in layer {
-            new_modlist.append(module.as_xml());
+    match resolve_order(
+        &mods,
+        &ModuleDescription::default(),
+        ResolveOptions::default(),
+    ) {
+        Ok((_, skipped)) => {
+            for skip in skipped
+                .iter()
+                .filter(|skip| skip.reason == SkipReason::Missing)
+            {
+                let warning = Warning::MissingDependency {
+                    module: skip.module.name.clone(),
+                    module_uuid: skip.module.uuid.clone(),
+                    dependency: skip.dependency.name.clone(),
+                    dependency_uuid: skip.dependency.uuid.clone(),
+                };
+                println!("{warning}");
+                problems += 1;
+            }
+        }
+        Err(e) => {
+            println!("{e}");
+            problems += 1;
         }
     }
 
-    *modlist = new_modlist;
+    let workshop = mods
+        .iter()
+        .filter(|module| module.description.is_workshop())
+        .count();
+    println!(
+        "Checked {} mods ({} local, {workshop} from the Workshop)",
+        mods.len(),
+        mods.len() - workshop
+    );
+
+    if problems > 0 {
+        return Err(format!("Found {problems} problem(s)").into());
+    }
 
-    let mut writer: BufWriter<Box<dyn Write>> = if args.write {
-        BufWriter::new(Box::new(File::create(&modsettings_path)?))
-    } else {
-        BufWriter::new(Box::new(io::stdout().lock()))
+    Ok(())
+}
+
+// What json_order writes. Update it along with json_order
+const ORDER_SCHEMA: &str = r#"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "title": "lsxwriter load order",
+  "type": "array",
+  "items": {
+    "type": "object",
+    "required": ["folder", "name", "uuid", "version64", "publish_handle"],
+    "properties": {
+      "folder": { "type": "string" },
+      "name": { "type": "string" },
+      "uuid": { "type": "string" },
+      "version64": { "type": "string" },
+      "publish_handle": { "type": ["string", "null"] }
+    }
+  }
+}"#;
+
+// The subset of SARIF 2.1.0 sarif_report writes. Update it along with sarif_report
+const VALIDATE_REPORT_SCHEMA: &str = r#"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "title": "lsxwriter validate report",
+  "type": "object",
+  "required": ["version", "runs"],
+  "properties": {
+    "$schema": { "type": "string" },
+    "version": { "const": "2.1.0" },
+    "runs": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "required": ["tool", "results"],
+        "properties": {
+          "tool": {
+            "type": "object",
+            "required": ["driver"],
+            "properties": {
+              "driver": {
+                "type": "object",
+                "required": ["name", "version", "rules"],
+                "properties": {
+                  "name": { "const": "lsxwriter" },
+                  "version": { "type": "string" },
+                  "rules": {
+                    "type": "array",
+                    "items": {
+                      "type": "object",
+                      "required": ["id"],
+                      "properties": { "id": { "type": "string" } }
+                    }
+                  }
+                }
+              }
+            }
+          },
+          "results": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "required": ["ruleId", "level", "message", "locations"],
+              "properties": {
+                "ruleId": { "type": "string" },
+                "level": { "enum": ["error", "warning"] },
+                "message": {
+                  "type": "object",
+                  "required": ["text"],
+                  "properties": { "text": { "type": "string" } }
+                },
+                "locations": {
+                  "type": "array",
+                  "items": {
+                    "type": "object",
+                    "required": ["physicalLocation"],
+                    "properties": {
+                      "physicalLocation": {
+                        "type": "object",
+                        "required": ["artifactLocation"],
+                        "properties": {
+                          "artifactLocation": {
+                            "type": "object",
+                            "required": ["uri"],
+                            "properties": { "uri": { "type": "string" } }
+                          }
+                        }
+                      },
+                      "logicalLocations": {
+                        "type": "array",
+                        "items": {
+                          "type": "object",
+                          "required": ["name", "kind"],
+                          "properties": {
+                            "name": { "type": "string" },
+                            "kind": { "const": "module" }
+                          }
+                        }
+                      }
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}"#;
+
+// SARIF 2.1.0 log with one run, listing the rules that were hit and a result per problem
+fn sarif_report(problems: &[Problem]) -> String {
+    let mut rules: Vec<&str> = Vec::new();
+    for problem in problems {
+        if !rules.contains(&problem.rule_id) {
+            rules.push(problem.rule_id);
+        }
+    }
+
+    let rules: Vec<String> = rules
+        .iter()
+        .map(|rule_id| format!("{{\"id\": {}}}", json::string(rule_id)))
+        .collect();
+
+    let results: Vec<String> = problems
+        .iter()
+        .map(|problem| {
+            let uri = problem.path.to_string_lossy().replace('\\', "/");
+            let logical_locations = problem.uuid.as_ref().map_or_else(String::new, |uuid| {
+                format!(
+                    ", \"logicalLocations\": [{{\"name\": {}, \"kind\": \"module\"}}]",
+                    json::string(uuid)
+                )
+            });
+            format!(
+                "{{\"ruleId\": {}, \"level\": \"{}\", \"message\": {{\"text\": {}}}, \
+                \"locations\": [{{\"physicalLocation\": {{\"artifactLocation\": {{\"uri\": {}}}}}{logical_locations}}}]}}",
+                json::string(problem.rule_id),
+                if problem.error { "error" } else { "warning" },
+                json::string(&problem.message),
+                json::string(&uri)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"$schema\": \"https://json.schemastore.org/sarif-2.1.0.json\", \"version\": \"2.1.0\", \
+        \"runs\": [{{\"tool\": {{\"driver\": {{\"name\": \"lsxwriter\", \"version\": {}, \"rules\": [{}]}}}}, \
+        \"results\": [{}]}}]}}",
+        json::string(env!("CARGO_PKG_VERSION")),
+        rules.join(", "),
+        results.join(", ")
+    )
+}
+
+fn version_check(base_mod: &ModuleDescription) -> Result<(), Box<dyn Error>> {
+    let Some(version) = base_mod.version() else {
+        return Err(format!("Could not read the version of {}", base_mod.name).into());
     };
 
-    write!(writer, "{modsettings}").unwrap();
+    let (min, max) = TESTED_BASE_VERSIONS;
 
-    writer.flush().unwrap();
+    println!("Game version ({}): {version}", base_mod.name);
+    println!("Tested with: {min} to {max}");
 
-    println!("Success");
+    if version > max {
+        eprintln!(
+            "Warning: the game is newer than any version lsxwriter has been tested with, the mod formats may have changed"
+        );
+    } else if version < min {
+        eprintln!("Warning: the game is older than any version lsxwriter has been tested with");
+    }
 
     Ok(())
 }
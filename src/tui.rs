@@ -0,0 +1,348 @@
+use lsxwriter::{Module, ModuleDescription, ResolvedMod};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+};
+use std::{collections::HashMap, io};
+
+const HELP: &str = "↑/↓ select  shift+↑/↓ move  x exclude  p pin  w write  q quit";
+
+struct Row {
+    description: ModuleDescription,
+    // Position in the resolved order, before any moves
+    load_index: usize,
+    warnings: Vec<String>,
+    excluded: bool,
+    pinned: bool,
+}
+
+struct App {
+    rows: Vec<Row>,
+    // Direct (non base game) dependencies of each mod, by uuid
+    dependencies: HashMap<String, Vec<ModuleDescription>>,
+    base_mod: ModuleDescription,
+    list_state: ListState,
+    status: Option<(String, bool)>,
+    confirming: bool,
+}
+
+// Shows the resolved load order and lets the user tweak it. Returns the order to write, or None
+// if the user quit without writing
+pub fn run(
+    mods: &[Module],
+    order: Vec<ResolvedMod>,
+    base_mod: &ModuleDescription,
+) -> io::Result<Option<Vec<ModuleDescription>>> {
+    let mut app = App {
+        rows: order
+            .into_iter()
+            .map(|resolved| Row {
+                description: resolved.description,
+                load_index: resolved.load_index,
+                warnings: resolved.warnings.iter().map(ToString::to_string).collect(),
+                excluded: false,
+                pinned: false,
+            })
+            .collect(),
+        dependencies: mods
+            .iter()
+            .map(|module| {
+                let dependencies = module
+                    .dependencies
+                    .iter()
+                    .filter(|dependency| !dependency.is_base_game())
+                    .cloned()
+                    .collect();
+                (module.description.uuid.clone(), dependencies)
+            })
+            .collect(),
+        base_mod: base_mod.clone(),
+        list_state: ListState::default().with_selected(Some(0)),
+        status: None,
+        confirming: false,
+    };
+
+    ratatui::run(|terminal| app.event_loop(terminal))
+}
+
+impl App {
+    fn event_loop(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+    ) -> io::Result<Option<Vec<ModuleDescription>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.confirming {
+                self.confirming = false;
+                if key.code == KeyCode::Char('y') {
+                    return Ok(Some(self.order()));
+                }
+                self.status = Some((String::from("Write cancelled"), false));
+                continue;
+            }
+
+            let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                KeyCode::Up if shift => self.move_selected(-1),
+                KeyCode::Down if shift => self.move_selected(1),
+                KeyCode::Char('K') => self.move_selected(-1),
+                KeyCode::Char('J') => self.move_selected(1),
+                KeyCode::Up | KeyCode::Char('k') => self.list_state.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list_state.select_next(),
+                KeyCode::Char('x') => self.toggle_excluded(),
+                KeyCode::Char('p') => self.toggle_pinned(),
+                KeyCode::Char('w') => self.confirm_write(),
+                _ => {}
+            }
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.list_state
+            .selected()
+            .unwrap_or(0)
+            .min(self.rows.len().saturating_sub(1))
+    }
+
+    fn order(&self) -> Vec<ModuleDescription> {
+        self.rows
+            .iter()
+            .filter(|row| !row.excluded)
+            .map(|row| row.description.clone())
+            .collect()
+    }
+
+    fn depends_on(&self, module: &ModuleDescription, dependency: &ModuleDescription) -> bool {
+        self.dependencies
+            .get(&module.uuid)
+            .is_some_and(|deps| deps.contains(dependency))
+    }
+
+    fn reject(&mut self, message: String) {
+        self.status = Some((message, true));
+    }
+
+    fn move_selected(&mut self, direction: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let from = self.selected();
+        let Some(to) = from
+            .checked_add_signed(direction)
+            .filter(|to| *to < self.rows.len())
+        else {
+            return;
+        };
+
+        // Swapping neighbours only changes the order of those two, so only a direct dependency
+        // between them can be violated
+        let (first, second) = (from.min(to), from.max(to));
+        let first_row = &self.rows[first];
+        let second_row = &self.rows[second];
+
+        if first_row.description == self.base_mod || second_row.description == self.base_mod {
+            return self.reject(String::from("The base game always loads first"));
+        }
+
+        if first_row.pinned || second_row.pinned {
+            let pinned = if first_row.pinned {
+                first_row
+            } else {
+                second_row
+            };
+            return self.reject(format!("{} is pinned", pinned.description.name));
+        }
+
+        if self.depends_on(&second_row.description, &first_row.description) {
+            return self.reject(format!(
+                "{} depends on {} and must load after it",
+                second_row.description.name, first_row.description.name
+            ));
+        }
+
+        self.rows.swap(first, second);
+        self.list_state.select(Some(to));
+        self.status = None;
+    }
+
+    fn toggle_excluded(&mut self) {
+        let Some(row) = self.rows.get(self.selected()) else {
+            return;
+        };
+
+        if row.description == self.base_mod {
+            return self.reject(String::from("The base game can't be excluded"));
+        }
+
+        let description = row.description.clone();
+
+        if row.excluded {
+            // Can't bring a mod back while something it needs is still excluded
+            if let Some(dependency) = self
+                .rows
+                .iter()
+                .find(|other| other.excluded && self.depends_on(&description, &other.description))
+            {
+                return self.reject(format!(
+                    "{} depends on {}, which is excluded",
+                    description.name, dependency.description.name
+                ));
+            }
+        } else if let Some(dependent) = self
+            .rows
+            .iter()
+            .find(|other| !other.excluded && self.depends_on(&other.description, &description))
+        {
+            return self.reject(format!(
+                "{} depends on {} so it can't be excluded",
+                dependent.description.name, description.name
+            ));
+        }
+
+        let selected = self.selected();
+        self.rows[selected].excluded = !self.rows[selected].excluded;
+        self.status = None;
+    }
+
+    fn toggle_pinned(&mut self) {
+        let selected = self.selected();
+        if let Some(row) = self.rows.get_mut(selected) {
+            row.pinned = !row.pinned;
+        }
+    }
+
+    fn confirm_write(&mut self) {
+        if self.order().iter().all(|module| *module == self.base_mod) {
+            return self.reject(String::from(
+                "Refusing to write a Mods list with no mods besides the base game",
+            ));
+        }
+
+        self.confirming = true;
+        self.status = Some((String::from("Write modsettings.lsx? (y/n)"), false));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, details] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut style = Style::default();
+                if row.excluded {
+                    style = style
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::CROSSED_OUT);
+                }
+
+                if !row.warnings.is_empty() && !row.excluded {
+                    style = style.fg(Color::Yellow);
+                }
+
+                let marker = if row.pinned { "📌 " } else { "" };
+                ListItem::new(format!("{marker}{}", row.description.name)).style(style)
+            })
+            .collect();
+
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title("Load order"))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut self.list_state,
+        );
+
+        frame.render_widget(
+            Paragraph::new(self.details())
+                .block(Block::bordered().title("Details"))
+                .wrap(Wrap { trim: false }),
+            details,
+        );
+
+        let status_line = match &self.status {
+            Some((message, true)) => Line::from(Span::styled(
+                message.as_str(),
+                Style::default().fg(Color::Red),
+            )),
+            Some((message, false)) => Line::from(message.as_str()),
+            None => Line::from(Span::styled(HELP, Style::default().fg(Color::DarkGray))),
+        };
+        frame.render_widget(status_line, status);
+    }
+
+    fn details(&self) -> Vec<Line<'_>> {
+        let Some(row) = self.rows.get(self.selected()) else {
+            return Vec::new();
+        };
+
+        let description = &row.description;
+        let mut lines = vec![
+            Line::from(format!("Name: {}", description.name)),
+            Line::from(format!("Folder: {}", description.folder)),
+            Line::from(format!("UUID: {}", description.uuid)),
+            Line::from(format!("Version64: {}", description.version64)),
+            Line::from(format!("Resolved position: {}", row.load_index + 1)),
+        ];
+
+        if let Some(author) = &description.author {
+            lines.push(Line::from(format!("Author: {author}")));
+        }
+
+        if let Some(text) = &description.description {
+            lines.push(Line::from(""));
+            lines.push(Line::from(text.as_str()));
+        }
+
+        if !row.warnings.is_empty() {
+            lines.extend([Line::from(""), Line::from("Warnings:")]);
+            for warning in &row.warnings {
+                lines.push(Line::from(Span::styled(
+                    format!("  {warning}"),
+                    Style::default().fg(Color::Yellow),
+                )));
+            }
+        }
+
+        lines.extend([Line::from(""), Line::from("Dependencies:")]);
+
+        for dependency in self
+            .dependencies
+            .get(&description.uuid)
+            .into_iter()
+            .flatten()
+        {
+            // Only scanned mods have an entry in the dependency map
+            if self.dependencies.contains_key(&dependency.uuid) {
+                lines.push(Line::from(format!("  {}", dependency.name)));
+            } else {
+                lines.push(Line::from(Span::styled(
+                    format!("  {} (missing)", dependency.name),
+                    Style::default().fg(Color::Yellow),
+                )));
+            }
+        }
+
+        lines
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fmt::Display, str::FromStr};
+
+// Larian's packed module version. Version64 stores it as major:7 minor:8 revision:16 build:31
+// (high to low bits, leaving the top two bits of the int64 LSX stores it as unused); the older
+// Version32 as major:4 minor:4 revision:8 build:16
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub revision: u16,
+    pub build: u32,
+}
+
+impl Version {
+    pub const fn new(major: u16, minor: u16, revision: u16, build: u32) -> Self {
+        Self {
+            major,
+            minor,
+            revision,
+            build,
+        }
+    }
+
+    pub const fn from_version64(packed: u64) -> Self {
+        Self {
+            major: ((packed >> 55) & 0x7f) as u16,
+            minor: ((packed >> 47) & 0xff) as u16,
+            revision: ((packed >> 31) & 0xffff) as u16,
+            build: (packed & 0x7fff_ffff) as u32,
+        }
+    }
+
+    // Fields wider than their bits are truncated, as they would be by the game, instead of
+    // spilling into the next field
+    pub const fn to_version64(self) -> u64 {
+        ((self.major as u64 & 0x7f) << 55)
+            | ((self.minor as u64 & 0xff) << 47)
+            | ((self.revision as u64 & 0xffff) << 31)
+            | (self.build as u64 & 0x7fff_ffff)
+    }
+
+    // Every Version32 field fits its Version64 field, so to_version64 widens it without loss
+    pub const fn from_version32(packed: u32) -> Self {
+        Self {
+            major: (packed >> 28) as u16,
+            minor: ((packed >> 24) & 0xf) as u16,
+            revision: ((packed >> 16) & 0xff) as u16,
+            build: (packed & 0xffff),
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.revision, self.build
+        )
+    }
+}
+
+// Parses the decimal Version64 string used in LSX files
+impl FromStr for Version {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Stored as int64, but the top bit is never used by a real version
+        Ok(Self::from_version64(s.trim().parse::<i64>()? as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_fields_round_trip() {
+        let max = Version::new(0x7f, 0xff, 0xffff, 0x7fff_ffff);
+        assert_eq!(max.to_version64(), (1 << 62) - 1);
+        assert_eq!(Version::from_version64(max.to_version64()), max);
+
+        for version in [
+            Version::new(0x7f, 0, 0, 0),
+            Version::new(0, 0xff, 0, 0),
+            Version::new(0, 0, 0xffff, 0),
+            Version::new(0, 0, 0, 0x7fff_ffff),
+        ] {
+            assert_eq!(Version::from_version64(version.to_version64()), version);
+        }
+    }
+
+    #[test]
+    fn overflowing_fields_are_truncated() {
+        let version = Version::new(0x80, 0x100, 0, 0x8000_0000);
+        assert_eq!(version.to_version64(), 0);
+
+        // A major of 128 or more would otherwise make the int64 negative
+        let version = Version::new(0xff, 0, 0, 0);
+        assert!((version.to_version64() as i64) > 0);
+        assert_eq!(Version::from_version64(version.to_version64()).major, 0x7f);
+    }
+
+    #[test]
+    fn unused_bits_are_ignored() {
+        assert_eq!(
+            Version::from_version64(0b11 << 62 | Version::new(1, 2, 3, 4).to_version64()),
+            Version::new(1, 2, 3, 4)
+        );
+    }
+
+    #[test]
+    fn version32_widens_to_version64() {
+        let version = Version::from_version32(0x1234_5678);
+        assert_eq!(version, Version::new(1, 2, 0x34, 0x5678));
+        assert_eq!(
+            version.to_version64(),
+            (1 << 55) | (2 << 47) | (0x34 << 31) | 0x5678
+        );
+
+        let max = Version::from_version32(u32::MAX);
+        assert_eq!(max, Version::new(0xf, 0xf, 0xff, 0xffff));
+        assert_eq!(Version::from_version64(max.to_version64()), max);
+    }
+
+    #[test]
+    fn parses_decimal_version64() {
+        assert_eq!("36028797018963968".parse(), Ok(Version::new(1, 0, 0, 0)));
+        assert_eq!(" 0 ".parse(), Ok(Version::default()));
+        assert!("1.0.0.0".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn orders_by_field_significance() {
+        let mut versions = vec![
+            Version::new(1, 0, 0, 1),
+            Version::new(0, 0xff, 0xffff, 0x7fff_ffff),
+            Version::new(1, 1, 0, 0),
+            Version::new(1, 0, 1, 0),
+        ];
+        versions.sort();
+        assert_eq!(
+            versions,
+            [
+                Version::new(0, 0xff, 0xffff, 0x7fff_ffff),
+                Version::new(1, 0, 0, 1),
+                Version::new(1, 0, 1, 0),
+                Version::new(1, 1, 0, 0),
+            ]
+        );
+
+        // Ordering versions agrees with ordering their packed values
+        for pair in versions.windows(2) {
+            assert!(pair[0].to_version64() < pair[1].to_version64());
+        }
+    }
+}
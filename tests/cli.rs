@@ -0,0 +1,700 @@
+// Runs the command line tool against scratch game data directories
+
+mod common;
+
+use common::*;
+use lsxwriter::{json::Value, version::Version};
+
+#[test]
+fn refuses_to_write_an_empty_mods_list() {
+    let game_data = GameData::new("empty");
+    let backup = game_data
+        .path()
+        .join("PlayerProfiles/Public/modsettings.lsx.bak");
+
+    let output = game_data.run(&["--write", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--allow-empty"));
+    assert!(!backup.exists());
+
+    let output = game_data.run(&["--write", "--no-cache", "--allow-empty"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(backup.exists());
+}
+
+#[test]
+fn info_follows_transitive_dependencies() {
+    let game_data = GameData::new("chain");
+    game_data.install("Top", &uuid(1), &[("Middle", &uuid(2))]);
+    game_data.install("Middle", &uuid(2), &[("Bottom", &uuid(3))]);
+    game_data.install("Bottom", &uuid(3), &[]);
+
+    let pak = game_data.path().join("Mods/Top.pak");
+    let output = run([
+        "info",
+        pak.to_str().unwrap(),
+        "--follow-dependencies",
+        "--game-data",
+        game_data.path().to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let tree = format!("\n  Middle ({})\n    Bottom ({})\n", uuid(2), uuid(3));
+    assert!(stdout(&output).contains(&tree));
+    assert!(stdout(&output).contains("Total dependencies (including transitive): 2"));
+}
+
+#[test]
+fn list_sorts_by_each_key() {
+    let game_data = GameData::new("list");
+    // (name, uuid, major version, bytes of padding)
+    for (name, n, major, padding) in [
+        ("Alpha", 3, 2, 0),
+        ("Beta", 1, 3, 5000),
+        ("Gamma", 2, 1, 1000),
+    ] {
+        let meta = meta(name, &uuid(n), &((major as i64) << 55).to_string(), &[]);
+        game_data.install_files(
+            &format!("{name}.pak"),
+            &[
+                (&format!("Mods/{name}/meta.lsx"), meta.as_bytes()),
+                (&format!("Public/{name}/padding"), &noise(padding)),
+            ],
+        );
+    }
+
+    let names = |sort_by: &str| -> Vec<String> {
+        let output = run([
+            "list",
+            game_data.path().to_str().unwrap(),
+            "--sort-by",
+            sort_by,
+        ]);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        stdout(&output)
+            .lines()
+            .map(|line| line.split('\t').next().unwrap().to_string())
+            .collect()
+    };
+
+    assert_eq!(names("name"), ["Alpha", "Beta", "Gamma"]);
+    assert_eq!(names("size"), ["Alpha", "Gamma", "Beta"]);
+    assert_eq!(names("version"), ["Gamma", "Alpha", "Beta"]);
+    assert_eq!(names("uuid"), ["Beta", "Gamma", "Alpha"]);
+}
+
+#[test]
+fn sizes_reports_each_mod_and_the_total() {
+    let game_data = GameData::new("sizes");
+    let mut expected = Vec::new();
+    for (name, padding) in [("Small", 100), ("Large", 4000)] {
+        let meta = meta(name, &uuid(padding), VERSION_1, &[]);
+        game_data.install_files(
+            &format!("{name}.pak"),
+            &[
+                (&format!("Mods/{name}/meta.lsx"), meta.as_bytes()),
+                (&format!("Public/{name}/padding"), &noise(padding as usize)),
+            ],
+        );
+        let on_disk = std::fs::metadata(game_data.path().join(format!("Mods/{name}.pak")))
+            .unwrap()
+            .len();
+        expected.push((name, on_disk, (meta.len() + padding as usize) as u64));
+    }
+
+    let output = run(["sizes", game_data.path().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let [
+        (small, small_disk, small_size),
+        (large, large_disk, large_size),
+    ] = expected[..]
+    else {
+        unreachable!()
+    };
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "On disk\tUncompressed\tName\n\
+            {large_disk}\t{large_size}\t{large}\n\
+            {small_disk}\t{small_size}\t{small}\n\
+            {}\t{}\tTotal (2 mods)\n",
+            small_disk + large_disk,
+            small_size + large_size
+        )
+    );
+}
+
+#[test]
+fn version_check_prints_the_game_version() {
+    let game_data = GameData::new("version");
+    let check = |version: Version| {
+        let version64 = version.to_version64().to_string();
+        let base = short_desc("GustavX", "GustavX", BASE_UUID, &version64);
+        game_data.set_modsettings(&modsettings(&[base]));
+        let output = game_data.run(&["--version-check", "--no-cache"]);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        (stdout(&output), stderr(&output))
+    };
+
+    let (printed, warnings) = check(Version::new(4, 1, 1, 6758295));
+    assert!(printed.contains("Game version (GustavX): 4.1.1.6758295\n"));
+    assert!(!warnings.contains("Warning"), "{warnings}");
+
+    let (printed, warnings) = check(Version::new(4, 2, 0, 0));
+    assert!(printed.contains("Game version (GustavX): 4.2.0.0\n"));
+    assert!(
+        warnings
+            .contains("Warning: the game is newer than any version lsxwriter has been tested with")
+    );
+
+    let (_, warnings) = check(Version::new(4, 1, 1, 6));
+    assert!(
+        warnings
+            .contains("Warning: the game is older than any version lsxwriter has been tested with")
+    );
+}
+
+#[test]
+fn apply_restores_modsettings_when_verification_fails() {
+    let game_data = GameData::new("apply");
+    // Resolving leaves the missing dependency out, verifying the written file catches it
+    game_data.install("Broken", &uuid(1), &[("Missing", &uuid(2))]);
+    let before = game_data.modsettings();
+
+    let output = game_data.run(&["apply"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("failed verification, restored the previous one"));
+    assert_eq!(game_data.modsettings(), before);
+
+    game_data.install("Missing", &uuid(2), &[]);
+    let output = game_data.run(&["apply"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(game_data.modsettings().contains(&uuid(1)));
+}
+
+#[test]
+fn markdown_format_prints_only_the_table() {
+    let game_data = GameData::new("markdown");
+    game_data.install("First", &uuid(1), &[]);
+    game_data.install("Second_Mod", &uuid(2), &[("First", &uuid(1))]);
+
+    let output = game_data.run(&["--format", "markdown", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "| # | Name | Version | UUID |\n\
+            |---|---|---|---|\n\
+            | 1 | GustavX | 1.0.0.0 | {BASE_UUID} |\n\
+            | 2 | First | 1.0.0.0 | {} |\n\
+            | 3 | Second\\_Mod | 1.0.0.0 | {} |\n",
+            uuid(1),
+            uuid(2)
+        )
+    );
+}
+
+#[test]
+fn manifest_replaces_the_mods_directory() {
+    let game_data = GameData::new("manifest-game");
+    game_data.install("Installed", &uuid(3), &[]);
+
+    let staging = Scratch::new("manifest-staging");
+    let mut manifest = String::from("# Staged mods\n");
+    for (name, n) in [("StagedA", 1), ("StagedB", 2)] {
+        let pak = staging.path().join(format!("{name}.pak"));
+        write_mod_pak(&pak, name, &uuid(n), &[]);
+        manifest.push_str(&format!("{}\n", pak.display()));
+    }
+    let manifest_path = staging.path().join("manifest.txt");
+    std::fs::write(&manifest_path, manifest).unwrap();
+
+    let output = game_data.run(&[
+        "--manifest",
+        manifest_path.to_str().unwrap(),
+        "--no-cache",
+        "--format",
+        "json",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let order = stdout(&output);
+    assert!(order.contains(&uuid(1)) && order.contains(&uuid(2)));
+    assert!(!order.contains(&uuid(3)));
+}
+
+#[test]
+fn validate_reports_problems_as_sarif() {
+    let game_data = GameData::new("sarif");
+    game_data.install("Dependent", &uuid(1), &[("Missing", &uuid(2))]);
+    game_data.set_modsettings(&modsettings(&[
+        base_short_desc(),
+        short_desc("Dependent", "Dependent", &uuid(1), VERSION_1),
+    ]));
+
+    let output = game_data.run(&["validate", "--format", "sarif"]);
+    let report = lsxwriter::json::parse(&stdout(&output)).expect("SARIF output isn't JSON");
+
+    assert_eq!(
+        report.get("version").and_then(|v| v.as_str()),
+        Some("2.1.0")
+    );
+    let run = &report.get("runs").and_then(|runs| runs.as_array()).unwrap()[0];
+    let driver = run.get("tool").and_then(|tool| tool.get("driver")).unwrap();
+    assert_eq!(
+        driver.get("name").and_then(|v| v.as_str()),
+        Some("lsxwriter")
+    );
+    let rules: Vec<&str> = driver
+        .get("rules")
+        .and_then(|rules| rules.as_array())
+        .unwrap()
+        .iter()
+        .map(|rule| rule.get("id").and_then(|id| id.as_str()).unwrap())
+        .collect();
+
+    let results = run
+        .get("results")
+        .and_then(|results| results.as_array())
+        .unwrap();
+    assert!(!results.is_empty());
+    for result in results {
+        let rule_id = result.get("ruleId").and_then(|v| v.as_str()).unwrap();
+        assert!(rules.contains(&rule_id));
+        let level = result.get("level").and_then(|v| v.as_str()).unwrap();
+        assert!(level == "error" || level == "warning");
+        assert!(result.get("message").and_then(|m| m.get("text")).is_some());
+        let location = &result.get("locations").and_then(|l| l.as_array()).unwrap()[0];
+        assert!(
+            location
+                .get("physicalLocation")
+                .and_then(|l| l.get("artifactLocation"))
+                .and_then(|l| l.get("uri"))
+                .and_then(|uri| uri.as_str())
+                .is_some()
+        );
+    }
+
+    let missing = results
+        .iter()
+        .find(|result| result.get("ruleId").and_then(|v| v.as_str()) == Some("missing-dependency"))
+        .expect("no missing-dependency result");
+    let module = missing
+        .get("locations")
+        .and_then(|l| l.as_array())
+        .and_then(|l| l[0].get("logicalLocations"))
+        .and_then(|l| l.as_array())
+        .and_then(|l| l[0].get("name"))
+        .and_then(|name| name.as_str());
+    assert_eq!(module, Some(uuid(1).as_str()));
+}
+
+#[test]
+fn lint_reports_each_mistake() {
+    let scratch = Scratch::new("lint");
+    let lint = |meta: &str, files: &[(&str, &[u8])]| {
+        let pak = scratch.path().join("Linted.pak");
+        let mut files = files.to_vec();
+        files.push(("Mods/Linted/meta.lsx", meta.as_bytes()));
+        write_pak(&pak, &files, PakOptions::default());
+        run(["lint", pak.to_str().unwrap()])
+    };
+
+    let clean = meta("Linted", &uuid(1), VERSION_1, &[]).replace(
+        r#"id="MD5" type="LSString" value="""#,
+        r#"id="MD5" type="LSString" value="0123456789abcdef0123456789abcdef""#,
+    );
+    let output = lint(&clean, &[("Public/Linted/data.txt", b"data")]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("No lints in "));
+
+    // Mismatched folder, empty MD5 and name, a malformed UUID and a file in another folder
+    let broken = meta("Linted", "not-a-guid", VERSION_1, &[])
+        .replace(
+            r#"value="Linted"/>
+          <attribute id="MD5""#,
+            r#"value="Declared"/>
+          <attribute id="MD5""#,
+        )
+        .replace(
+            r#"id="Name" type="LSString" value="Linted""#,
+            r#"id="Name" type="LSString" value="""#,
+        );
+    let output = lint(&broken, &[("Public/Other/data.txt", b"data")]);
+    assert_eq!(output.status.code(), Some(1));
+    let lints = stdout(&output);
+    for expected in [
+        "Folder is \"Declared\" but meta.lsx is in Mods/Linted/\n  Fix: ",
+        "Name is empty\n  Fix: give Name a value\n",
+        "MD5 is empty\n  Fix: ",
+        "UUID is not a valid GUID: not-a-guid\n  Fix: ",
+        "Public/Other/data.txt is outside the mod folder Declared\n  Fix: ",
+    ] {
+        assert!(lints.contains(expected), "missing {expected:?} in {lints}");
+    }
+    assert!(stderr(&output).contains("5 lint(s) in "));
+}
+
+#[test]
+fn parallel_runs_print_the_same_output() {
+    let game_data = GameData::new("jobs");
+    for n in 1..=8 {
+        let dependencies: Vec<(String, String)> = (1..n)
+            .step_by(3)
+            .map(|d| (format!("Mod{d}"), uuid(d)))
+            .collect();
+        let dependencies: Vec<(&str, &str)> = dependencies
+            .iter()
+            .map(|(name, uuid)| (name.as_str(), uuid.as_str()))
+            .collect();
+        game_data.install(&format!("Mod{n}"), &uuid(n), &dependencies);
+    }
+    // Unreadable paks, so there are warnings to order as well
+    for name in ["BrokenA.pak", "BrokenB.pak", "BrokenC.pak"] {
+        std::fs::write(game_data.path().join("Mods").join(name), noise(64)).unwrap();
+    }
+
+    let output = |jobs: &str| {
+        let output = game_data.run(&["--jobs", jobs, "--no-cache", "--format", "json"]);
+        (stdout(&output), stderr(&output))
+    };
+    let serial = output("1");
+    assert_eq!(serial.1.matches("Failed to parse pak file").count(), 3);
+    for _ in 0..5 {
+        assert_eq!(output("4"), serial);
+    }
+}
+
+#[test]
+fn extracted_files_keep_the_extraction_time() {
+    let scratch = Scratch::new("extract");
+    let pak = scratch.path().join("Extracted.pak");
+    let data = noise(300);
+    write_pak(
+        &pak,
+        &[
+            ("Mods/Extracted/meta.lsx", b"<save/>"),
+            ("Public/Extracted/data.bin", &data),
+        ],
+        PakOptions::default(),
+    );
+
+    // LSPK 18 records no modification times, so there is nothing older to restore
+    let before = std::time::SystemTime::now() - std::time::Duration::from_secs(2);
+    let out = scratch.path().join("out");
+    let output = run(["extract-all", pak.to_str().unwrap(), out.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("Extracted 2 files to "));
+
+    let extracted = out.join("Public/Extracted/data.bin");
+    assert_eq!(std::fs::read(&extracted).unwrap(), data);
+    for path in [out.join("Mods/Extracted/meta.lsx"), extracted] {
+        let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+        assert!(modified >= before);
+    }
+}
+
+#[test]
+fn reads_a_differently_cased_modsettings() {
+    let game_data = GameData::new("casing");
+    game_data.install("Cased", &uuid(1), &[]);
+    let cased = game_data
+        .path()
+        .join("PlayerProfiles/Public/ModSettings.lsx");
+    std::fs::rename(game_data.modsettings_path(), &cased).unwrap();
+
+    let output = game_data.run(&["--write", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stderr(&output).contains(&format!(
+        "Warning: using {} (the game names it modsettings.lsx)",
+        cased.display()
+    )));
+    assert!(std::fs::read_to_string(&cased).unwrap().contains(&uuid(1)));
+    assert!(!game_data.modsettings_path().exists());
+}
+
+// Checks value against the keywords of JSON Schema draft 7 the tool's schemas use
+fn conforms(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let keyword = |name: &str| schema.get(name);
+
+    if let Some(types) = keyword("type") {
+        let types: Vec<&str> = match types {
+            Value::String(ty) => vec![ty.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => return Err(format!("{path}: type isn't a string or array")),
+        };
+        let actual = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        if !types.contains(&actual) {
+            return Err(format!("{path}: {actual} isn't one of {types:?}"));
+        }
+    }
+    if let Some(expected) = keyword("const")
+        && value != expected
+    {
+        return Err(format!("{path}: {value:?} isn't {expected:?}"));
+    }
+    if let Some(allowed) = keyword("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!("{path}: {value:?} isn't one of {allowed:?}"));
+    }
+    for required in keyword("required").and_then(Value::as_array).unwrap_or(&[]) {
+        let name = required.as_str().unwrap();
+        if value.get(name).is_none() {
+            return Err(format!("{path}: missing {name}"));
+        }
+    }
+    if let Some(Value::Object(properties)) = keyword("properties") {
+        for (name, property) in properties {
+            if let Some(field) = value.get(name) {
+                conforms(field, property, &format!("{path}.{name}"))?;
+            }
+        }
+    }
+    if let (Some(items), Some(values)) = (keyword("items"), value.as_array()) {
+        for (i, item) in values.iter().enumerate() {
+            conforms(item, items, &format!("{path}[{i}]"))?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn json_outputs_match_their_schemas() {
+    let output = run(["json-schema"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let schemas = lsxwriter::json::parse(&stdout(&output)).expect("schemas aren't JSON");
+    for name in ["order", "validate"] {
+        let schema = schemas.get(name).unwrap();
+        assert_eq!(
+            schema.get("$schema").and_then(Value::as_str),
+            Some("http://json-schema.org/draft-07/schema#")
+        );
+        let alone = lsxwriter::json::parse(&stdout(&run(["json-schema", name]))).unwrap();
+        assert_eq!(&alone, schema);
+    }
+
+    let game_data = GameData::new("schema");
+    game_data.install("Dependent", &uuid(1), &[("Missing", &uuid(2))]);
+    game_data.install("Published", &uuid(3), &[]);
+
+    let order =
+        lsxwriter::json::parse(&stdout(&game_data.run(&["--format", "json", "--no-cache"])))
+            .expect("order isn't JSON");
+    assert_eq!(order.as_array().map(<[Value]>::len), Some(3));
+    conforms(&order, schemas.get("order").unwrap(), "order").unwrap();
+
+    game_data.set_modsettings(&modsettings(&[
+        base_short_desc(),
+        short_desc("Dependent", "Dependent", &uuid(1), VERSION_1),
+    ]));
+    let report =
+        lsxwriter::json::parse(&stdout(&game_data.run(&["validate", "--format", "sarif"])))
+            .expect("report isn't JSON");
+    conforms(&report, schemas.get("validate").unwrap(), "report").unwrap();
+}
+
+#[test]
+fn later_mods_dirs_override_earlier_ones() {
+    let game_data = GameData::new("layers");
+    let shared = Scratch::new("layers-shared");
+    let personal = Scratch::new("layers-personal");
+    let install = |dir: &Scratch, name: &str, n: u32, major: i64| {
+        let meta = meta(name, &uuid(n), &(major << 55).to_string(), &[]);
+        write_pak(
+            &dir.path().join(format!("{name}.pak")),
+            &[(&format!("Mods/{name}/meta.lsx"), meta.as_bytes())],
+            PakOptions::default(),
+        );
+    };
+    install(&shared, "Library", 1, 2);
+    install(&shared, "Other", 2, 1);
+    install(&personal, "LibraryFork", 1, 1);
+
+    let names = |extra: &[&str]| {
+        let mut args = vec![
+            "--mods-dir",
+            shared.path().to_str().unwrap(),
+            "--mods-dir",
+            personal.path().to_str().unwrap(),
+            "--no-cache",
+            "--format",
+            "json",
+        ];
+        args.extend(extra);
+        let output = game_data.run(&args);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        let order = lsxwriter::json::parse(&stdout(&output)).unwrap();
+        let names: Vec<String> = order
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|module| {
+                module
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        (names, stderr(&output))
+    };
+
+    // Replacing a mod from an earlier directory isn't a duplicate --strict fails on
+    let (order, messages) = names(&["--strict"]);
+    assert_eq!(order, ["GustavX", "LibraryFork", "Other"]);
+    assert!(messages.contains("LibraryFork"), "{messages}");
+
+    // Across directories by version instead
+    let (order, _) = names(&["--prefer-version", "highest"]);
+    assert_eq!(order, ["GustavX", "Library", "Other"]);
+}
+
+#[test]
+fn exit_status_tells_clean_warning_and_error_runs_apart() {
+    let game_data = GameData::new("exit-status");
+    game_data.install("Clean", &uuid(1), &[]);
+
+    let output = game_data.run(&["--no-cache", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Success: 1 mods in the load order\n"));
+
+    // The order is still produced around the missing dependency
+    game_data.install("Broken", &uuid(2), &[("Missing", &uuid(3))]);
+    let output = game_data.run(&["--no-cache"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let summary =
+        "Finished with problems: 2 mods in the load order, 0 unreadable pak(s), 1 warning(s)\n";
+    assert!(stdout(&output).contains(summary));
+
+    let output = game_data.run(&["--no-cache", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).contains(summary));
+    assert!(stderr(&output).contains("Failing because of --deny-warnings"));
+
+    game_data.set_modsettings("not a modsettings.lsx");
+    let output = game_data.run(&["--no-cache", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Error: "));
+}
+
+#[test]
+fn empty_mods_directory_keeps_the_base_game() {
+    let game_data = GameData::new("no-mods");
+
+    let output = game_data.run(&["--write", "--no-cache", "--allow-empty"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Warning: no non-base mods found"));
+    assert_eq!(game_data.modsettings(), modsettings(&[base_short_desc()]));
+}
+
+// Every file under dir with its contents
+fn snapshot(dir: &std::path::Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(snapshot(&path));
+        } else {
+            files.push((path.clone(), std::fs::read(&path).unwrap()));
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn resolve_only_prints_the_order_and_writes_nothing() {
+    let game_data = GameData::new("resolve-only");
+    game_data.install("Resolved", &uuid(1), &[]);
+    let before = snapshot(game_data.path());
+
+    let output = game_data.run(&["--write", "--resolve-only", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(snapshot(game_data.path()), before);
+
+    let order = lsxwriter::json::parse(&stdout(&output)).expect("order isn't JSON");
+    let uuids: Vec<&str> = order
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|module| module.get("uuid").and_then(Value::as_str).unwrap())
+        .collect();
+    assert_eq!(uuids, [BASE_UUID, uuid(1).as_str()]);
+}
+
+#[test]
+fn header_priority_orders_paks_not_the_entry_flags() {
+    let game_data = GameData::new("priority");
+    // Alpha has the higher header priority, Beta's entries the higher compression level in the
+    // high nibble of their flags
+    for (name, n, priority, entry_flags) in [("Alpha", 1, 5, 0x12), ("Beta", 2, 0, 0x42)] {
+        let meta = meta(name, &uuid(n), VERSION_1, &[]);
+        write_pak(
+            &game_data.path().join(format!("Mods/{name}.pak")),
+            &[(&format!("Mods/{name}/meta.lsx"), meta.as_bytes())],
+            PakOptions {
+                priority,
+                entry_flags,
+                ..Default::default()
+            },
+        );
+    }
+
+    let output = game_data.run(&["--format", "markdown", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let table = stdout(&output);
+    let position = |name: &str| table.find(&format!("| {name} |")).unwrap();
+    assert!(position("Beta") < position("Alpha"), "{table}");
+}
+
+#[test]
+fn info_shows_the_build() {
+    let scratch = Scratch::new("build");
+    let pak = scratch.path().join("Built.pak");
+    let meta = meta("Built", &uuid(1), VERSION_1, &[]).replace(
+        r#"<attribute id="Version64""#,
+        r#"<attribute id="Build" type="int32" value="42"/>
+          <attribute id="Version64""#,
+    );
+    write_pak(
+        &pak,
+        &[("Mods/Built/meta.lsx", meta.as_bytes())],
+        PakOptions::default(),
+    );
+
+    let output = run(["info", pak.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).contains("\nBuild: 42\n"));
+}
+
+#[test]
+fn duplicate_attribute_ids_warn_and_fail_strict() {
+    let game_data = GameData::new("duplicate-attribute");
+    let meta = meta("Doubled", &uuid(1), VERSION_1, &[]).replace(
+        r#"<attribute id="Version64""#,
+        &format!(
+            r#"<attribute id="UUID" type="FixedString" value="{}"/>
+          <attribute id="Version64""#,
+            uuid(2)
+        ),
+    );
+    game_data.install_files("Doubled.pak", &[("Mods/Doubled/meta.lsx", meta.as_bytes())]);
+    let warning = "Doubled's ModuleInfo has 2 attributes with id \"UUID\", only the first is used";
+
+    let output = game_data.run(&["--no-cache"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).contains(&format!("Warning: {warning}\n")));
+    assert!(stdout(&output).contains(&uuid(1)) && !stdout(&output).contains(&uuid(2)));
+
+    let output = game_data.run(&["--no-cache", "--strict"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains(warning));
+    assert!(stderr(&output).contains("1 warning(s), failing because of --strict"));
+}
@@ -0,0 +1,316 @@
+// Scratch game data directories and paks for the integration tests
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+pub const BASE_UUID: &str = "cb555efe-2d9e-131f-8195-a89329d218ea";
+
+// Version64 of 1.0.0.0
+pub const VERSION_1: &str = "36028797018963968";
+
+// Bytes lz4 can't shrink, to make a pak bigger
+pub fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+pub fn uuid(n: u32) -> String {
+    format!("00000000-0000-0000-0000-{n:012}")
+}
+
+// ModuleShortDesc node listing a mod, indented the way the tool writes modsettings.lsx
+pub fn short_desc(folder: &str, name: &str, uuid: &str, version64: &str) -> String {
+    format!(
+        r#"            <node id="ModuleShortDesc">
+              <attribute id="Folder" type="LSString" value="{folder}"/>
+              <attribute id="MD5" type="LSString" value=""/>
+              <attribute id="Name" type="LSString" value="{name}"/>
+              <attribute id="PublishHandle" type="uint64" value="0"/>
+              <attribute id="UUID" type="guid" value="{uuid}"/>
+              <attribute id="Version64" type="int64" value="{version64}"/>
+            </node>
+"#
+    )
+}
+
+// modsettings.lsx listing the given ModuleShortDesc nodes
+pub fn modsettings(short_descs: &[String]) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="8" revision="0" build="10"/>
+  <region id="ModuleSettings">
+    <node id="root">
+      <children>
+        <node id="Mods">
+          <children>
+{}          </children>
+        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#,
+        short_descs.concat()
+    )
+}
+
+pub fn base_short_desc() -> String {
+    short_desc("GustavX", "GustavX", BASE_UUID, VERSION_1)
+}
+
+// meta.lsx of a mod, dependencies are (name, uuid)
+pub fn meta(name: &str, uuid: &str, version64: &str, dependencies: &[(&str, &str)]) -> String {
+    let dependencies: String = dependencies
+        .iter()
+        .map(|(name, uuid)| short_desc(name, name, uuid, VERSION_1))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<save>
+  <version major="4" minor="0" revision="9" build="328"/>
+  <region id="Config">
+    <node id="root">
+      <children>
+        <node id="Dependencies">
+          <children>
+{dependencies}          </children>
+        </node>
+        <node id="ModuleInfo">
+          <attribute id="Author" type="LSWString" value="Tester"/>
+          <attribute id="Folder" type="LSString" value="{name}"/>
+          <attribute id="MD5" type="LSString" value=""/>
+          <attribute id="Name" type="LSString" value="{name}"/>
+          <attribute id="UUID" type="FixedString" value="{uuid}"/>
+          <attribute id="Version64" type="int64" value="{version64}"/>
+        </node>
+      </children>
+    </node>
+  </region>
+</save>
+"#
+    )
+}
+
+// What write_pak puts in the header's MD5 of the file list
+#[derive(Clone, Copy, PartialEq)]
+pub enum Checksum {
+    // All zero, which readers take as not filled in
+    Missing,
+    Correct,
+    Wrong,
+}
+
+// How write_pak stores each file
+#[derive(Clone, Copy)]
+pub struct PakOptions {
+    pub priority: u8,
+    pub archive_flags: u8,
+    // Compression flags written for every entry, and whether the data really is lz4 compressed
+    pub entry_flags: u8,
+    pub compress: bool,
+    // Uncompressed size recorded for every entry instead of the real one
+    pub uncompressed_size: Option<u32>,
+    // Bytes appended after each entry's data and counted in its size on disk, like aligning packers
+    pub padding: usize,
+    pub checksum: Checksum,
+}
+
+impl Default for PakOptions {
+    fn default() -> Self {
+        Self {
+            priority: 0,
+            archive_flags: 0,
+            entry_flags: 0x02,
+            compress: true,
+            uncompressed_size: None,
+            padding: 0,
+            checksum: Checksum::Missing,
+        }
+    }
+}
+
+// Version 18 pak holding the given files
+pub fn write_pak(path: &Path, files: &[(&str, &[u8])], options: PakOptions) {
+    write_split_pak(path, &[files], path.parent().unwrap(), options);
+}
+
+// Version 18 pak split into parts, the files of the first in the pak itself and those of part N in
+// <name>_N.pak in part_dir
+pub fn write_split_pak(
+    path: &Path,
+    parts: &[&[(&str, &[u8])]],
+    part_dir: &Path,
+    options: PakOptions,
+) {
+    let mut data = vec![0; 40];
+    let mut file_list = Vec::new();
+    let mut file_count = 0;
+    for (part, files) in parts.iter().enumerate() {
+        let mut part_data = Vec::new();
+        for (name, content) in *files {
+            let mut stored = if options.compress {
+                lz4_flex::compress(content)
+            } else {
+                content.to_vec()
+            };
+            stored.resize(stored.len() + options.padding, 0);
+            let part_data = if part == 0 { &mut data } else { &mut part_data };
+            let offset = part_data.len() as u64;
+            part_data.extend_from_slice(&stored);
+
+            let mut entry = [0; 272];
+            entry[..name.len()].copy_from_slice(name.as_bytes());
+            entry[256..260].copy_from_slice(&(offset as u32).to_le_bytes());
+            entry[260..262].copy_from_slice(&((offset >> 32) as u16).to_le_bytes());
+            entry[262] = part as u8;
+            entry[263] = options.entry_flags;
+            entry[264..268].copy_from_slice(&(stored.len() as u32).to_le_bytes());
+            let uncompressed_size = options.uncompressed_size.unwrap_or(content.len() as u32);
+            entry[268..272].copy_from_slice(&uncompressed_size.to_le_bytes());
+            file_list.extend_from_slice(&entry);
+            file_count += 1;
+        }
+
+        if part > 0 {
+            let stem = path.file_stem().unwrap().to_str().unwrap();
+            fs::write(part_dir.join(format!("{stem}_{part}.pak")), part_data).unwrap();
+        }
+    }
+
+    let file_list_offset = data.len() as u64;
+    let file_list = lz4_flex::compress(&file_list);
+    data.extend_from_slice(&(file_count as u32).to_le_bytes());
+    data.extend_from_slice(&(file_list.len() as u32).to_le_bytes());
+    data.extend_from_slice(&file_list);
+
+    data[0..4].copy_from_slice(b"LSPK");
+    data[4..8].copy_from_slice(&18u32.to_le_bytes());
+    data[8..16].copy_from_slice(&file_list_offset.to_le_bytes());
+    data[16..20].copy_from_slice(&(file_list.len() as u32 + 8).to_le_bytes());
+    data[20] = options.archive_flags;
+    data[21] = options.priority;
+    let mut md5 = lsxwriter::md5::digest(&file_list);
+    match options.checksum {
+        Checksum::Missing => md5 = [0; 16],
+        Checksum::Correct => {}
+        Checksum::Wrong => md5[0] ^= 0xff,
+    }
+    data[22..38].copy_from_slice(&md5);
+    data[38..40].copy_from_slice(&(parts.len() as u16).to_le_bytes());
+
+    fs::write(path, data).unwrap();
+}
+
+// Pak of a mod with only its meta.lsx
+pub fn write_mod_pak(path: &Path, name: &str, uuid: &str, dependencies: &[(&str, &str)]) {
+    let meta = meta(name, uuid, VERSION_1, dependencies);
+    write_pak(
+        path,
+        &[(&format!("Mods/{name}/meta.lsx"), meta.as_bytes())],
+        PakOptions::default(),
+    );
+}
+
+// Directory removed again when dropped
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("lsxwriter-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Game data directory with a Mods directory and a modsettings.lsx listing only the base game
+pub struct GameData(Scratch);
+
+impl GameData {
+    pub fn new(name: &str) -> Self {
+        let scratch = Scratch::new(name);
+        fs::create_dir_all(scratch.path().join("Mods")).unwrap();
+        fs::create_dir_all(scratch.path().join("PlayerProfiles/Public")).unwrap();
+        let game_data = Self(scratch);
+        game_data.set_modsettings(&modsettings(&[base_short_desc()]));
+        game_data
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    pub fn modsettings_path(&self) -> PathBuf {
+        self.path().join("PlayerProfiles/Public/modsettings.lsx")
+    }
+
+    pub fn modsettings(&self) -> String {
+        fs::read_to_string(self.modsettings_path()).unwrap()
+    }
+
+    pub fn set_modsettings(&self, contents: &str) {
+        fs::write(self.modsettings_path(), contents).unwrap();
+    }
+
+    // Puts a pak of the mod in the Mods directory
+    pub fn install(&self, name: &str, uuid: &str, dependencies: &[(&str, &str)]) {
+        write_mod_pak(
+            &self.path().join("Mods").join(format!("{name}.pak")),
+            name,
+            uuid,
+            dependencies,
+        );
+    }
+
+    // Puts a pak holding the files in the Mods directory
+    pub fn install_files(&self, pak_name: &str, files: &[(&str, &[u8])]) {
+        write_pak(
+            &self.path().join("Mods").join(pak_name),
+            files,
+            PakOptions::default(),
+        );
+    }
+
+    // Runs the tool with the game data directory as the last argument
+    pub fn run(&self, args: &[&str]) -> Output {
+        run(args.iter().copied().chain([self.path().to_str().unwrap()]))
+    }
+}
+
+pub fn run<'a>(args: impl IntoIterator<Item = &'a str>) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lsxwriter"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,383 @@
+// Reads paks written by the tests through the library
+
+mod common;
+
+use common::*;
+use lsxwriter::{CompressionMethod, Error, Notice, PAKError, PAKFile};
+
+#[test]
+fn zero_length_entries_read_as_empty() {
+    let scratch = Scratch::new("zero-length");
+
+    for (name, options) in [
+        (
+            "stored.pak",
+            PakOptions {
+                entry_flags: 0,
+                compress: false,
+                ..Default::default()
+            },
+        ),
+        ("lz4.pak", PakOptions::default()),
+    ] {
+        let path = scratch.path().join(name);
+        write_pak(&path, &[("Public/Mod/empty.txt", b"")], options);
+
+        let pak = PAKFile::open(path).unwrap();
+        let entry = pak.find_entry("Public/Mod/empty.txt").unwrap();
+        assert_eq!(pak.read_entry(entry).unwrap(), b"");
+    }
+}
+
+#[test]
+fn mod_folders_lists_each_folder_once() {
+    let scratch = Scratch::new("mod-folders");
+    let path = scratch.path().join("two.pak");
+    write_pak(
+        &path,
+        &[
+            ("Mods/First/meta.lsx", b"<save/>"),
+            ("Public/Other/Stats/data.txt", b"data"),
+            ("Mods/Second/meta.lsx", b"<save/>"),
+            ("Mods/First/Story/story.div", b"story"),
+        ],
+        PakOptions::default(),
+    );
+
+    assert_eq!(
+        PAKFile::open(path).unwrap().mod_folders(),
+        ["First", "Second"]
+    );
+}
+
+#[test]
+fn single_entry_pak() {
+    let scratch = Scratch::new("single-entry");
+    let path = scratch.path().join("Only.pak");
+    write_mod_pak(&path, "Only", &uuid(1), &[]);
+    let meta = meta("Only", &uuid(1), VERSION_1, &[]);
+
+    let pak = PAKFile::open(path).unwrap();
+    assert_eq!(pak.entries().unwrap().count(), 1);
+    assert_eq!(pak.stats().file_count, 1);
+    assert_eq!(pak.stats().uncompressed_size, meta.len() as u64);
+    assert_eq!(pak.mod_folders(), ["Only"]);
+    assert!(pak.file_names().contains("Mods/Only/meta.lsx"));
+
+    let entry = pak.find_entry("Mods/Only/meta.lsx").unwrap();
+    assert_eq!(pak.read_entry(entry).unwrap(), meta.as_bytes());
+    assert!(pak.find_entry("Mods/Only/other.lsx").is_none());
+
+    assert!(pak.is_module());
+    let module = pak.module().unwrap();
+    assert_eq!(module.description.name, "Only");
+    assert_eq!(module.description.uuid, uuid(1));
+    assert!(module.dependencies.is_empty());
+}
+
+#[test]
+fn forced_compression_overrides_wrong_flags() {
+    let scratch = Scratch::new("force-compression");
+    let path = scratch.path().join("zlib-flagged.pak");
+    let meta = meta("Mod", &uuid(1), VERSION_1, &[]);
+    // lz4 data flagged as zlib
+    let options = PakOptions {
+        entry_flags: 0x01,
+        ..Default::default()
+    };
+    write_pak(&path, &[("Mods/Mod/meta.lsx", meta.as_bytes())], options);
+
+    let mut pak = PAKFile::open(path).unwrap();
+    let entry = pak.find_entry("Mods/Mod/meta.lsx").unwrap().clone();
+    assert!(pak.read_entry(&entry).is_err());
+
+    pak.force_compression = Some(CompressionMethod::Lz4);
+    assert_eq!(pak.read_entry(&entry).unwrap(), meta.as_bytes());
+}
+
+#[test]
+fn encrypted_archives_are_unsupported() {
+    let scratch = Scratch::new("encrypted");
+    let path = scratch.path().join("encrypted.pak");
+    let options = PakOptions {
+        archive_flags: 0x80,
+        ..Default::default()
+    };
+    write_pak(&path, &[("Mods/Mod/meta.lsx", b"<save/>")], options);
+
+    let Err(error) = PAKFile::open(path) else {
+        panic!("opened an encrypted pak");
+    };
+    assert!(matches!(error, Error::Pak(PAKError::Unsupported(_))));
+    assert!(error.to_string().contains("encrypted"));
+}
+
+#[test]
+fn unknown_compression_methods_are_unsupported() {
+    let scratch = Scratch::new("unknown-compression");
+    let path = scratch.path().join("future.pak");
+    // Method 4 at the default level
+    let options = PakOptions {
+        entry_flags: 0x24,
+        ..Default::default()
+    };
+    write_pak(&path, &[("Mods/Mod/meta.lsx", &noise(100))], options);
+
+    let pak = PAKFile::open(path).unwrap();
+    let entry = pak.find_entry("Mods/Mod/meta.lsx").unwrap();
+    assert!(matches!(
+        entry.compression_method(),
+        Err(PAKError::UnsupportedCompression(4))
+    ));
+    let Err(error) = pak.read_entry(entry) else {
+        panic!("read an entry with an unknown compression method");
+    };
+    assert!(matches!(
+        error,
+        Error::Pak(PAKError::UnsupportedCompression(4))
+    ));
+}
+
+#[test]
+fn parts_are_found_in_the_archive_part_dir() {
+    let scratch = Scratch::new("archive-parts");
+    let parts_dir = scratch.path().join("parts");
+    std::fs::create_dir(&parts_dir).unwrap();
+    let path = scratch.path().join("Split.pak");
+    let meta = meta("Split", &uuid(1), VERSION_1, &[]);
+    let (first, second) = (noise(200), noise(300));
+    write_split_pak(
+        &path,
+        &[
+            &[("Mods/Split/meta.lsx", meta.as_bytes())],
+            &[("Public/Split/first.bin", &first)],
+            &[("Public/Split/second.bin", &second)],
+        ],
+        &parts_dir,
+        PakOptions::default(),
+    );
+
+    let Err(error) = PAKFile::open(path.clone()) else {
+        panic!("opened a pak without its parts");
+    };
+    assert!(matches!(
+        error,
+        Error::Pak(PAKError::MissingPart(part)) if part == scratch.path().join("Split_1.pak")
+    ));
+
+    let pak = PAKFile::open_with(path, false, Some(&parts_dir)).unwrap();
+    for (name, content) in [
+        ("Mods/Split/meta.lsx", meta.as_bytes()),
+        ("Public/Split/first.bin", &first),
+        ("Public/Split/second.bin", &second),
+    ] {
+        let entry = pak.find_entry(name).unwrap();
+        assert_eq!(pak.read_entry(entry).unwrap(), content);
+    }
+    assert_eq!(pak.module().unwrap().description.name, "Split");
+}
+
+#[test]
+fn metadata_past_4_gib_is_read_from_the_full_offset() {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let scratch = Scratch::new("large-offset");
+    let path = scratch.path().join("Large.pak");
+    let meta = meta("Large", &uuid(1), VERSION_1, &[]);
+    let compressed = lz4_flex::compress(meta.as_bytes());
+    // Sparse, only the header, the metadata and the file list take up space
+    let offset: u64 = 0x1_0000_0010;
+
+    let mut entry = [0; 272];
+    let name = b"Mods/Large/meta.lsx";
+    entry[..name.len()].copy_from_slice(name);
+    entry[256..260].copy_from_slice(&(offset as u32).to_le_bytes());
+    entry[260..262].copy_from_slice(&((offset >> 32) as u16).to_le_bytes());
+    entry[263] = 0x02;
+    entry[264..268].copy_from_slice(&(compressed.len() as u32).to_le_bytes());
+    entry[268..272].copy_from_slice(&(meta.len() as u32).to_le_bytes());
+    let file_list = lz4_flex::compress(&entry);
+    let file_list_offset = offset + compressed.len() as u64;
+
+    let mut header = [0; 40];
+    header[0..4].copy_from_slice(b"LSPK");
+    header[4..8].copy_from_slice(&18u32.to_le_bytes());
+    header[8..16].copy_from_slice(&file_list_offset.to_le_bytes());
+    header[16..20].copy_from_slice(&(file_list.len() as u32 + 8).to_le_bytes());
+    header[38..40].copy_from_slice(&1u16.to_le_bytes());
+
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(&header).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(&compressed).unwrap();
+    file.write_all(&1u32.to_le_bytes()).unwrap();
+    file.write_all(&(file_list.len() as u32).to_le_bytes())
+        .unwrap();
+    file.write_all(&file_list).unwrap();
+    drop(file);
+
+    let pak = PAKFile::open(path).unwrap();
+    assert_eq!(pak.file_list[0].offset(), offset);
+    let module = pak.module().unwrap();
+    assert_eq!(module.description.name, "Large");
+    assert_eq!(module.description.uuid, uuid(1));
+}
+
+#[test]
+fn file_list_checksum_is_verified() {
+    let scratch = Scratch::new("checksum");
+    let verify = |checksum: Checksum| {
+        let path = scratch.path().join("checked.pak");
+        let options = PakOptions {
+            checksum,
+            ..Default::default()
+        };
+        write_pak(&path, &[("Mods/Mod/meta.lsx", b"<save/>")], options);
+        PAKFile::open(path).unwrap().verify_checksum()
+    };
+
+    verify(Checksum::Correct).unwrap();
+    verify(Checksum::Missing).unwrap();
+    assert!(matches!(
+        verify(Checksum::Wrong),
+        Err(Error::Pak(PAKError::ChecksumMismatch { expected, actual })) if expected != actual
+    ));
+}
+
+#[test]
+fn stored_files_flagged_as_lz4_are_read_as_is() {
+    let scratch = Scratch::new("stored-as-lz4");
+    let path = scratch.path().join("mislabelled.pak");
+    let data = noise(500);
+    let options = PakOptions {
+        compress: false,
+        ..Default::default()
+    };
+    write_pak(&path, &[("Public/Mod/data.bin", &data)], options);
+
+    let pak = PAKFile::open(path).unwrap();
+    let entry = pak.find_entry("Public/Mod/data.bin").unwrap();
+    assert_eq!(pak.read_entry(entry).unwrap(), data);
+    assert_eq!(
+        pak.take_notices(),
+        [Notice::StoredDespiteFlags(String::from(
+            "Public/Mod/data.bin"
+        ))]
+    );
+}
+
+#[test]
+fn undersized_lz4_entries_are_read_in_full() {
+    let scratch = Scratch::new("undersized");
+    let path = scratch.path().join("undersized.pak");
+    let data = noise(5000);
+    let options = PakOptions {
+        uncompressed_size: Some(100),
+        ..Default::default()
+    };
+    write_pak(&path, &[("Public/Mod/data.bin", &data)], options);
+
+    let pak = PAKFile::open(path).unwrap();
+    let entry = pak.find_entry("Public/Mod/data.bin").unwrap();
+    assert_eq!(pak.read_entry(entry).unwrap(), data);
+    assert_eq!(
+        pak.take_notices(),
+        [Notice::UndersizedLz4 {
+            file: String::from("Public/Mod/data.bin"),
+            declared: 100,
+            actual: 5000,
+        }]
+    );
+}
+
+#[test]
+fn padding_after_compressed_streams_is_ignored() {
+    use std::io::Write;
+
+    let scratch = Scratch::new("padded");
+    let data = noise(2000);
+    let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    zlib.write_all(&data).unwrap();
+    let zlib = zlib.finish().unwrap();
+    let zstd = zstd::encode_all(&data[..], 0).unwrap();
+
+    for (name, flags, compressed) in [("zlib.pak", 0x01, zlib), ("zstd.pak", 0x03, zstd)] {
+        let path = scratch.path().join(name);
+        // Already compressed above, the pak only stores it
+        let options = PakOptions {
+            entry_flags: flags,
+            compress: false,
+            uncompressed_size: Some(data.len() as u32),
+            padding: 13,
+            ..Default::default()
+        };
+        write_pak(&path, &[("Public/Mod/data.bin", &compressed)], options);
+
+        let pak = PAKFile::open(path).unwrap();
+        let entry = pak.find_entry("Public/Mod/data.bin").unwrap();
+        assert_eq!(entry.size_on_disk as usize, compressed.len() + 13);
+        assert_eq!(pak.read_entry(entry).unwrap(), data, "{name}");
+    }
+}
+
+#[test]
+fn scan_magic_finds_a_pak_after_a_stub() {
+    let scratch = Scratch::new("scan-magic");
+    let pak = scratch.path().join("plain.pak");
+    let data = noise(700);
+    write_pak(
+        &pak,
+        &[
+            (
+                "Mods/Stubbed/meta.lsx",
+                meta("Stubbed", &uuid(1), VERSION_1, &[]).as_bytes(),
+            ),
+            ("Public/Stubbed/data.bin", &data),
+        ],
+        PakOptions::default(),
+    );
+
+    // An installer or self-extractor in front of the pak, masked so it can't contain the magic
+    let mut stubbed = b"MZ".to_vec();
+    stubbed.extend(noise(3000).iter().map(|byte| byte & 0x3f));
+    stubbed.extend(std::fs::read(&pak).unwrap());
+    let path = scratch.path().join("stubbed.exe");
+    std::fs::write(&path, stubbed).unwrap();
+
+    let Err(error) = PAKFile::open(path.clone()) else {
+        panic!("opened a stubbed pak without scanning for the magic");
+    };
+    assert!(matches!(error, Error::Pak(PAKError::BadMagic(_))));
+
+    let pak = PAKFile::open_with(path, true, None).unwrap();
+    let entry = pak.find_entry("Public/Stubbed/data.bin").unwrap();
+    assert_eq!(pak.read_entry(entry).unwrap(), data);
+    assert_eq!(pak.module().unwrap().description.name, "Stubbed");
+}
+
+#[test]
+fn unicode_folders_resolve_to_a_module() {
+    let scratch = Scratch::new("unicode");
+    let path = scratch.path().join("Unicode.pak");
+    let folder = "Тав_Волосы_髪型";
+    let meta = meta(folder, &uuid(1), VERSION_1, &[]);
+    write_pak(
+        &path,
+        &[
+            (&format!("Public/{folder}/Stats/données.txt"), b"data"),
+            (&format!("Mods/{folder}/meta.lsx"), meta.as_bytes()),
+        ],
+        PakOptions::default(),
+    );
+
+    let pak = PAKFile::open(path).unwrap();
+    assert_eq!(pak.mod_folders(), [folder]);
+    let entry = pak
+        .find_entry(&format!("Public/{folder}/Stats/données.txt"))
+        .unwrap();
+    assert_eq!(pak.read_entry(entry).unwrap(), b"data");
+
+    let module = pak.module().unwrap();
+    assert_eq!(module.description.folder, folder);
+    assert_eq!(module.description.name, folder);
+}